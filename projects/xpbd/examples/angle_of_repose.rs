@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use std::time::Duration;
+use xpbd::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Pours three kinds of grains onto their own floors and logs the angle each
+// heap settles at: marbles that roll freely and run flat, sand at around 30
+// degrees, and damp sand steeper still
+fn main() {
+    App::new()
+        .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(vector_xy(0., -500.)))
+        .insert_resource(GrainRng(StdRng::seed_from_u64(0)))
+        .insert_resource(SolverConfig { deterministic: true, ..default() })
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_systems(Startup, startup)
+        .add_systems(FixedUpdate, pour_grains.run_if(on_timer(Duration::from_millis(50))))
+        .add_systems(FixedUpdate, measure_heaps.run_if(on_timer(Duration::from_secs(1))))
+        .add_systems(FixedUpdate, despawn_grains)
+        .run();
+}
+
+const GRAIN_RADIUS: Scalar = 2.5;
+const GRAINS_PER_HEAP: usize = 300;
+const FLOOR_TOP: Scalar = -200.;
+
+struct HeapKind {
+    name: &'static str,
+    x: Scalar,
+    granular: Granular,
+}
+
+fn heap_kinds() -> [HeapKind; 3] {
+    [
+        HeapKind { name: "marbles", x: -300., granular: Granular { rolling_resistance: 0., ..default() } },
+        HeapKind { name: "sand", x: 0., granular: Granular::default() },
+        HeapKind { name: "damp sand", x: 300., granular: Granular { cohesion: 0.05, cohesion_distance: 1., ..default() } },
+    ]
+}
+
+// Which heap a grain was poured onto
+#[derive(Component)]
+struct Heap(usize);
+
+// Seeded so every run pours the same grains
+#[derive(Resource)]
+struct GrainRng(StdRng);
+
+#[derive(Resource)]
+struct Materials {
+    blue: Handle<ColorMaterial>,
+}
+
+#[derive(Resource)]
+struct Meshes {
+    circle: Handle<Mesh>,
+}
+
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
+
+    commands.insert_resource(Meshes {
+        circle: meshes.add(Mesh::from(Circle::new(to_f32(GRAIN_RADIUS))))
+    });
+    commands.insert_resource(Materials {
+        blue: blue.clone()
+    });
+
+    let size = box_size(250., 50.);
+    for kind in heap_kinds() {
+        commands.spawn((
+            Name::new("Floor"),
+            Mesh2d(meshes.add(Mesh::from(Rectangle::new(to_f32(size.x), to_f32(size.y))))),
+            MeshMaterial2d(blue.clone()),
+            StaticBoxBundle {
+                pos: Pos(vector_xy(kind.x, FLOOR_TOP - size.y / 2.)),
+                collider: BoxCollider { size },
+                ..default()
+            }
+        ));
+    }
+
+    commands.spawn((
+        Name::new("Camera"),
+        Camera2d,
+        Transform::from_xyz(0., 0., 100.),
+    ));
+}
+
+fn pour_grains(
+    mut commands: Commands,
+    materials: Res<Materials>,
+    meshes: Res<Meshes>,
+    mut rng: ResMut<GrainRng>,
+    grains: Query<(), With<Heap>>,
+) {
+    if grains.iter().count() >= GRAINS_PER_HEAP * heap_kinds().len() {
+        return;
+    }
+
+    for (index, kind) in heap_kinds().into_iter().enumerate() {
+        let pos = vector_xy(kind.x + (rng.0.random::<Scalar>() - 0.5) * 4., FLOOR_TOP + 50.);
+        commands.spawn((
+            Name::new("Grain"),
+            Mesh2d(meshes.circle.clone()),
+            MeshMaterial2d(materials.blue.clone()),
+            ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., GRAIN_RADIUS),
+            kind.granular,
+            Heap(index),
+            Transform::from_translation(to_translation(pos, 0.))
+        ));
+    }
+}
+
+// The slope of a heap is its height over half its width at the base, counting
+// only grains that have come to rest
+fn measure_heaps(query: Query<(&Pos, &PrevPos, &Heap)>) {
+    for (index, kind) in heap_kinds().into_iter().enumerate() {
+        let grains: Vec<Vector> = query
+            .iter()
+            .filter(|(pos, prev_pos, heap)| heap.0 == index && pos.0.distance(prev_pos.0) < 0.01)
+            .map(|(pos, ..)| pos.0)
+            .collect();
+        let height = grains.iter().map(|pos| pos.y - FLOOR_TOP).fold(0., Scalar::max);
+        let half_width = grains.iter().map(|pos| (pos.x - kind.x).abs()).fold(0., Scalar::max);
+        if half_width > 0. {
+            info!("{}: {} grains at {:.1} degrees", kind.name, grains.len(), height.atan2(half_width).to_degrees());
+        }
+    }
+}
+
+// Grains rolling off the end of a floor
+fn despawn_grains(mut commands: Commands, query: Query<(Entity, &Pos), With<Heap>>) {
+    for (entity, pos) in query.iter() {
+        if pos.0.y < FLOOR_TOP - 200. {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use xpbd::*;
+
+// Run with `cargo run --example ball_pit_3d --features 3d`
+fn main() {
+    App::new()
+        .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(Vector::new(0., -500., 0.)))
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_systems(Startup, startup)
+        .run();
+}
+
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
+    let red = materials.add(Color::srgb(0.8, 0.3, 0.3));
+
+    commands.spawn((
+        Name::new("Camera"),
+        Camera3d::default(),
+        Transform::from_xyz(0., 250., 500.).looking_at(Vec3::new(0., 50., 0.), Vec3::Y),
+    ));
+    commands.spawn((
+        Name::new("Light"),
+        DirectionalLight { shadows_enabled: true, ..default() },
+        Transform::from_xyz(100., 300., 200.).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    // A floor with four low walls around it
+    for (pos, size) in [
+        (Vector::new(0., -10., 0.), Vector::new(300., 20., 300.)),
+        (Vector::new(-160., 20., 0.), Vector::new(20., 80., 300.)),
+        (Vector::new(160., 20., 0.), Vector::new(20., 80., 300.)),
+        (Vector::new(0., 20., -160.), Vector::new(340., 80., 20.)),
+        (Vector::new(0., 20., 160.), Vector::new(340., 80., 20.)),
+    ] {
+        commands.spawn((
+            Name::new("Wall"),
+            Mesh3d(meshes.add(Cuboid::from_size(to_render(size)))),
+            MeshMaterial3d(blue.clone()),
+            StaticCuboidBundle { pos: Pos(pos), collider: CuboidCollider { size }, ..default() },
+        ));
+    }
+
+    let radius = 10.;
+    let ball = meshes.add(Sphere::new(to_f32(radius)));
+    for i in 0..200 {
+        let (x, z, y) = (i % 8, (i / 8) % 5, i / 40);
+        // Offset every layer a little so the balls tumble instead of stacking
+        let pos = Vector::new(x as Scalar * 25. - 87.5 + y as Scalar * 3., 100. + y as Scalar * 25., z as Scalar * 25. - 50.);
+        commands.spawn((
+            Name::new("Ball"),
+            Mesh3d(ball.clone()),
+            MeshMaterial3d(red.clone()),
+            ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., radius),
+            Transform::from_translation(to_render(pos)),
+        ));
+    }
+}
@@ -2,13 +2,16 @@ use bevy::prelude::*;
 use xpbd::*;
 
 fn main() {
-    App::new()
-        .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
-        .insert_resource(Gravity(Vec2::new(0., 0.)))
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(vector_xy(0., 0.)))
         .add_plugins(DefaultPlugins)
         .add_plugins(XPBDPlugin)
-        .add_systems(Startup, startup)  
-        .run();
+        .add_systems(Startup, startup);
+    // Debug drawing and grabbing are 2D only
+    #[cfg(not(feature = "3d"))]
+    app.add_plugins((XPBDDebugPlugin, XPBDGrabPlugin));
+    app.run();
 }
 
 fn startup(
@@ -17,15 +20,15 @@ fn startup(
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
-    let size = Vec2::new(350., 100.);
+    let size = box_size(350., 100.);
 
     commands.spawn((
         Name::new("Floor"),
-        Mesh2d(meshes.add(Mesh::from(Rectangle::new(size.x, size.y)))),
+        Mesh2d(meshes.add(Mesh::from(Rectangle::new(to_f32(size.x), to_f32(size.y))))),
         MeshMaterial2d(blue.clone()),
         StaticBoxBundle {
-            pos: Pos(Vec2::new(0., -62.)),
-            collider: BoxCollider { size: size  },
+            pos: Pos(vector_xy(0., -62.)),
+            collider: BoxCollider { size },
             ..default()
         }
     ));
@@ -46,18 +49,18 @@ fn startup(
 
     for i in 0..15 {
         for j in 0..stacks {
-            let pos = Vec2::new(
-                (j as f32 - stacks as f32 / 2.) * 2.5 * radius,
-                2. * radius * i as f32 - 2.
+            let pos = vector_xy(
+                (j as Scalar - stacks as Scalar / 2.) * 2.5 * radius,
+                2. * radius * i as Scalar - 2.
             );
-            let vel = Vec2::ZERO;
+            let vel = Vector::ZERO;
 
             commands.spawn((
                 Name::new("Circle"),
-                Mesh2d(meshes.add(Mesh::from(Circle::new(radius).clone()))),
+                Mesh2d(meshes.add(Mesh::from(Circle::new(to_f32(radius))))),
                 MeshMaterial2d(blue.clone()),
                 ParticleBundle::new_with_pos_vel_mass_radius(pos, vel, 10., radius),
-                Transform::from_translation(pos.extend(0.))
+                Transform::from_translation(to_translation(pos, 0.))
             ));
         }
     }
@@ -4,7 +4,7 @@ use xpbd::*;
 fn main() {
     App::new()
         .insert_resource(ClearColor(Color::BLACK))
-        .insert_resource(Gravity(Vec2::ZERO))
+        .insert_resource(Gravity(Vector::ZERO))
         .add_plugins(DefaultPlugins)
         .add_plugins(XPBDPlugin)
         .add_systems(Startup, startup)
@@ -25,7 +25,7 @@ fn startup(
         Name::new("Left"),
         Mesh2d(circle.clone()),
         MeshMaterial2d(white.clone()),
-        ParticleBundle::new_with_pos_vel_mass(Vec2::new(-250., 0.), Vec2::new(60.0, 0.0), 250.),
+        ParticleBundle::new_with_pos_vel_mass(vector_xy(-250., 0.), vector_xy(60.0, 0.0), 250.),
         Transform::from_xyz(0.0, 0.0, 0.0),
     ));
 
@@ -33,9 +33,9 @@ fn startup(
         Name::new("Right"),
         Mesh2d(circle.clone()),
         MeshMaterial2d(red.clone()),
-        ParticleBundle::new_with_pos_vel_mass(Vec2::new(250.0, 0.0), Vec2::new(-60.0, 0.0), 100.),
+        ParticleBundle::new_with_pos_vel_mass(vector_xy(250.0, 0.0), vector_xy(-60.0, 0.0), 100.),
         Transform::from_xyz(0.0, 0.0, 0.0),
     ));
 
-    commands.spawn((Name::new("Camera"), Camera2d::default()));
+    commands.spawn((Name::new("Camera"), Camera2d));
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+use xpbd::*;
+
+fn main() {
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(vector_xy(0., -500.)))
+        .insert_resource(FluidConfig::new(2.5, 1.))
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_systems(Startup, startup);
+    // Grabbing is 2D only
+    #[cfg(not(feature = "3d"))]
+    app.add_plugins(XPBDGrabPlugin);
+    app.run();
+}
+
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
+    let water = materials.add(Color::srgb(0.2, 0.5, 0.9));
+    let droplet = meshes.add(Mesh::from(Circle::new(2.5)));
+
+    commands.spawn((
+        Name::new("Camera"),
+        Camera2d,
+        Transform::from_translation(Vec3::new(0., 100., 100.)),
+    ));
+
+    // An open tank
+    for (pos, size) in [
+        (vector_xy(0., -10.), box_size(400., 20.)),
+        (vector_xy(-210., 120.), box_size(20., 280.)),
+        (vector_xy(210., 120.), box_size(20., 280.)),
+    ] {
+        commands.spawn((
+            Name::new("Wall"),
+            Mesh2d(meshes.add(Mesh::from(Rectangle::new(to_f32(size.x), to_f32(size.y))))),
+            MeshMaterial2d(blue.clone()),
+            StaticBoxBundle { pos: Pos(pos), collider: BoxCollider { size }, ..default() },
+        ));
+    }
+
+    // Dam break: a block of water against the left wall
+    for column in 0..30 {
+        for row in 0..40 {
+            let pos = vector_xy(-197.5 + column as Scalar * 5., 2.5 + row as Scalar * 5.);
+            commands.spawn((
+                Name::new("Droplet"),
+                FluidParticle,
+                Mesh2d(droplet.clone()),
+                MeshMaterial2d(water.clone()),
+                ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., 2.5),
+                Transform::from_translation(to_translation(pos, 0.)),
+            ));
+        }
+    }
+
+    // Something to float around in it
+    let pos = vector_xy(100., 150.);
+    commands.spawn((
+        Name::new("Ball"),
+        Mesh2d(meshes.add(Mesh::from(Circle::new(15.)))),
+        MeshMaterial2d(blue),
+        ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 5., 15.),
+        Transform::from_translation(to_translation(pos, 0.)),
+    ));
+}
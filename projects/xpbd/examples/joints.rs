@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+use xpbd::*;
+
+fn main() {
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(vector_xy(0., -500.)))
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_systems(Startup, startup);
+    // Debug drawing and grabbing are 2D only
+    #[cfg(not(feature = "3d"))]
+    app.add_plugins((XPBDDebugPlugin, XPBDGrabPlugin));
+    app.run();
+}
+
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
+    let radius = 10.;
+    let circle = meshes.add(Mesh::from(Circle::new(to_f32(radius))));
+
+    commands.spawn((
+        Name::new("Camera"),
+        Camera2d,
+        Transform::from_translation(Vec3::new(0., 0., 100.)),
+    ));
+
+    let spawn_anchor = |commands: &mut Commands, pos: Vector| {
+        // Anchors have no collider, so the bodies hanging off them can swing through
+        commands.spawn((Name::new("Anchor"), Pos(pos))).id()
+    };
+    let spawn_ball = |commands: &mut Commands, pos: Vector| {
+        commands.spawn((
+            Name::new("Ball"),
+            Mesh2d(circle.clone()),
+            MeshMaterial2d(blue.clone()),
+            ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., radius),
+            Transform::from_translation(to_translation(pos, 0.)),
+        )).id()
+    };
+
+    // Revolute joints are 2D only
+    #[cfg(not(feature = "3d"))]
+    {
+        // Pendulum
+        let anchor = spawn_anchor(&mut commands, vector_xy(-300., 150.));
+        let bob = spawn_ball(&mut commands, vector_xy(-220., 150.));
+        commands.spawn(RevoluteJoint::new(anchor, bob, 80.));
+
+        // Door: a hinge that only opens a quarter turn
+        let anchor = spawn_anchor(&mut commands, vector_xy(-100., 150.));
+        let door = spawn_ball(&mut commands, vector_xy(-20., 150.));
+        commands.spawn(RevoluteJoint::new(anchor, door, 80.).with_limits(Scalar::to_radians(-90.), 0.));
+
+        // Motor spinning a wheel at one turn per second
+        let anchor = spawn_anchor(&mut commands, vector_xy(100., 150.));
+        let wheel = spawn_ball(&mut commands, vector_xy(150., 150.));
+        commands.spawn(RevoluteJoint::new(anchor, wheel, 50.).with_motor(Scalar::to_radians(360.), 0.));
+    }
+
+    // Slider along a slope
+    let anchor = spawn_anchor(&mut commands, vector_xy(250., 150.));
+    let slider = spawn_ball(&mut commands, vector_xy(250., 150.));
+    commands.spawn(PrismaticJoint::new(anchor, slider, vector_xy(1., -1.)).with_limits(0., 150.));
+
+    // Chain of welded balls hanging from a soft distance joint
+    let anchor = spawn_anchor(&mut commands, vector_xy(0., -50.));
+    let mut previous = spawn_ball(&mut commands, vector_xy(0., -110.));
+    commands.spawn(DistanceJoint::new(anchor, previous, 60.).with_compliance(0.001));
+    for i in 1..4 {
+        let ball = spawn_ball(&mut commands, vector_xy(i as Scalar * 2. * radius, -110.));
+        commands.spawn(FixedJoint::new(previous, ball, vector_xy(2. * radius, 0.)));
+        previous = ball;
+    }
+}
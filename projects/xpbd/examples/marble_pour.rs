@@ -2,12 +2,15 @@ use bevy::prelude::*;
 use bevy::time::common_conditions::on_timer;
 use std::time::Duration;
 use xpbd::*;
-use rand::random;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 fn main() {
     App::new()
         .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
         .insert_resource(Gravity::default())
+        .insert_resource(MarbleRng(StdRng::seed_from_u64(0)))
+        .insert_resource(SolverConfig { deterministic: true, ..default() })
         .add_plugins(DefaultPlugins)
         .add_plugins(XPBDPlugin)
         .add_systems(Startup, startup)
@@ -17,6 +20,10 @@ fn main() {
         .run();
 }
 
+// Seeded so every run pours the same marbles
+#[derive(Resource)]
+struct MarbleRng(StdRng);
+
 #[derive(Resource)]
 struct Materials {
     blue: Handle<ColorMaterial>,
@@ -35,21 +42,21 @@ fn startup(
     let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
 
     commands.insert_resource(Meshes {
-        circle: meshes.add(Mesh::from(Circle::new(2.5).clone()))
+        circle: meshes.add(Mesh::from(Circle::new(2.5)))
     });
     commands.insert_resource(Materials {
         blue: blue.clone()
     });
 
-    let size = Vec2::new(350., 100.);
+    let size = box_size(350., 100.);
 
     commands.spawn((
         Name::new("Floor"),
-        Mesh2d(meshes.add(Mesh::from(Rectangle::new(size.x, size.y)))),
+        Mesh2d(meshes.add(Mesh::from(Rectangle::new(to_f32(size.x), to_f32(size.y))))),
         MeshMaterial2d(blue.clone()),
         StaticBoxBundle {
-            pos: Pos(Vec2::new(0., -355.)),
-            collider: BoxCollider { size: size  },
+            pos: Pos(vector_xy(0., -355.)),
+            collider: BoxCollider { size },
             ..default()
         }
     ));
@@ -96,11 +103,12 @@ fn visible_area(
 fn spawn_marbles(
     mut commands: Commands,
     materials: Res<Materials>,
-    meshes: Res<Meshes>
+    meshes: Res<Meshes>,
+    mut rng: ResMut<MarbleRng>
 ) {
-    let radius = 2.5;
-    let pos = Vec2::new(random::<f32>() - 0.5, random::<f32>() - 0.5) * 25. + Vec2::Y * 3.;
-    let vel = Vec2::new(random::<f32>() - 0.5, random::<f32>() - 0.5);
+    let radius: Scalar = 2.5;
+    let pos = vector_xy(rng.0.random::<Scalar>() - 0.5, rng.0.random::<Scalar>() - 0.5) * 25. + Vector::Y * 3.;
+    let vel = vector_xy(rng.0.random::<Scalar>() - 0.5, rng.0.random::<Scalar>() - 0.5);
 
     info!("Spawn marble: pos={:?}, vel={:?}", pos, vel);
 
@@ -109,7 +117,9 @@ fn spawn_marbles(
         Mesh2d(meshes.circle.clone()),
         MeshMaterial2d(materials.blue.clone()),
         ParticleBundle::new_with_pos_vel_mass_radius(pos, vel, 1., radius),
-        Transform::from_translation(pos.extend(0.))
+        // Frictional marbles pile up at their angle of repose instead of flowing flat
+        Granular::default(),
+        Transform::from_translation(to_translation(pos, 0.))
     ));
 }
 
@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+use xpbd::*;
+
+fn main() {
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(vector_xy(0., -500.)))
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_systems(Startup, startup)
+        .add_systems(Update, log_tears);
+    // Debug drawing and grabbing are 2D only
+    #[cfg(not(feature = "3d"))]
+    app.add_plugins((XPBDDebugPlugin, XPBDGrabPlugin));
+    app.run();
+}
+
+fn startup(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Camera"),
+        Camera2d,
+        Transform::from_translation(Vec3::new(0., 0., 100.)),
+    ));
+
+    // A bridge hanging between two pins
+    RopeBuilder::new(vector_xy(-350., 150.), vector_xy(-50., 150.), 30)
+        .pin_start()
+        .pin_end()
+        .with_particle_mass(0.5)
+        .spawn(&mut commands);
+
+    // A curtain that tears when dragged too hard
+    ClothBuilder::new(vector_xy(50., 200.), 20, 20, 12.)
+        .pin_top_row(4)
+        .with_particle_mass(0.2)
+        .with_bend_compliance(0.01)
+        .with_break_force(4000.)
+        .spawn(&mut commands);
+}
+
+fn log_tears(mut broken: MessageReader<JointBroken>) {
+    for joint in broken.read() {
+        info!("Tore joint {:?} at {:.0}", joint.joint, joint.force);
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use xpbd::*;
+
+// Run with `--features hot-reload` and edit assets/scenes/ball_stacking.physics.ron
+// while this runs to reload the scene
+fn main() {
+    App::new()
+        .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity::default())
+        .add_plugins(DefaultPlugins.set(AssetPlugin {
+            watch_for_changes_override: Some(true),
+            ..default()
+        }))
+        .add_plugins(XPBDPlugin)
+        .add_plugins(PhysicsScenePlugin)
+        .add_systems(Startup, startup)
+        .add_systems(Update, add_meshes)
+        .run();
+}
+
+#[derive(Resource)]
+struct Materials {
+    blue: Handle<ColorMaterial>,
+}
+
+fn startup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(Materials {
+        blue: materials.add(Color::srgb(0.4, 0.4, 0.6)),
+    });
+
+    commands.spawn((
+        Name::new("Scene"),
+        PhysicsSceneRoot(asset_server.load("scenes/ball_stacking.physics.ron")),
+    ));
+
+    commands.spawn((Name::new("Camera"), Camera2d));
+}
+
+// Scene files only describe physics, so give every new body something to render
+fn add_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<Materials>,
+    circles: Query<(Entity, &CircleCollider), Without<Mesh2d>>,
+    boxes: Query<(Entity, &BoxCollider), Without<Mesh2d>>,
+) {
+    for (entity, circle) in circles.iter() {
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Circle::new(to_f32(circle.radius)))),
+            MeshMaterial2d(materials.blue.clone()),
+        ));
+    }
+    for (entity, box_collider) in boxes.iter() {
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Rectangle::new(to_f32(box_collider.size.x), to_f32(box_collider.size.y)))),
+            MeshMaterial2d(materials.blue.clone()),
+        ));
+    }
+}
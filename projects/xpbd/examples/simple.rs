@@ -4,7 +4,7 @@ use xpbd::*;
 fn main() {
     App::new()
         .insert_resource(ClearColor(Color::BLACK))
-        .insert_resource(Gravity(Vec2::ZERO))
+        .insert_resource(Gravity(Vector::ZERO))
         .add_plugins(DefaultPlugins)
         .add_plugins(XPBDPlugin)
         .add_systems(Startup, startup)
@@ -24,9 +24,10 @@ fn startup(
         Name::new("Circle"),
         Mesh2d(circle.clone()),
         MeshMaterial2d(white.clone()),
-        ParticleBundle::new_with_pos_and_vel(Vec2::ZERO, Vec2::new(60.0, 0.0)),
+        ParticleBundle::new_with_pos_and_vel(Vector::ZERO, vector_xy(60.0, 0.0)),
         Transform::from_xyz(0.0, 0.0, 0.0),
+        TransformInterpolation::Interpolate,
     ));
 
-    commands.spawn((Name::new("Camera"), Camera2d::default()));
+    commands.spawn((Name::new("Camera"), Camera2d));
 }
\ No newline at end of file
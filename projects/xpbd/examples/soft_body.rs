@@ -0,0 +1,64 @@
+#[cfg(not(feature = "3d"))]
+use bevy::prelude::*;
+#[cfg(not(feature = "3d"))]
+use xpbd::*;
+
+// Soft bodies are 2D only
+#[cfg(feature = "3d")]
+fn main() {
+    eprintln!("This example is 2D only, run it without the `3d` feature");
+}
+
+#[cfg(not(feature = "3d"))]
+fn main() {
+    App::new()
+        .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity(vector_xy(0., -500.)))
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_plugins(XPBDGrabPlugin)
+        .add_systems(Startup, startup)
+        .run();
+}
+
+#[cfg(not(feature = "3d"))]
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
+    let size = box_size(600., 100.);
+
+    commands.spawn((
+        Name::new("Floor"),
+        Mesh2d(meshes.add(Mesh::from(Rectangle::new(to_f32(size.x), to_f32(size.y))))),
+        MeshMaterial2d(blue.clone()),
+        StaticBoxBundle {
+            pos: Pos(vector_xy(0., -250.)),
+            collider: BoxCollider { size },
+            ..default()
+        }
+    ));
+
+    commands.spawn((
+        Name::new("Camera"),
+        Camera2d,
+        Transform::from_translation(Vec3::new(0., 0., 100.)),
+    ));
+
+    // A slightly soft, over-inflated blob
+    let blob = SoftBodyBuilder::ring(vector_xy(-120., 50.), 60., 24)
+        .with_compliance(0.0001)
+        .with_area(0.0001, 1.1);
+    let mesh = meshes.add(blob.mesh());
+    let entity = blob.spawn(&mut commands);
+    commands.entity(entity).insert((Mesh2d(mesh), MeshMaterial2d(materials.add(Color::srgb(0.9, 0.4, 0.4)))));
+
+    // A jelly cube
+    let jelly = SoftBodyBuilder::grid(vector_xy(60., 0.), 8, 8, 15.)
+        .with_compliance(0.0005);
+    let mesh = meshes.add(jelly.mesh());
+    let entity = jelly.spawn(&mut commands);
+    commands.entity(entity).insert((Mesh2d(mesh), MeshMaterial2d(materials.add(Color::srgb(0.4, 0.8, 0.4)))));
+}
@@ -0,0 +1,77 @@
+#[cfg(not(feature = "3d"))]
+use bevy::prelude::*;
+#[cfg(not(feature = "3d"))]
+use xpbd::*;
+
+// '#' is solid, anything else is empty
+#[cfg(not(feature = "3d"))]
+const LEVEL: [&str; 12] = [
+    "#..........................#",
+    "#..........................#",
+    "#..........................#",
+    "#.......######.............#",
+    "#..........................#",
+    "#..................#####...#",
+    "#..........................#",
+    "#....###...................#",
+    "#....###..........##.......#",
+    "#................####......#",
+    "#...............######.....#",
+    "############################",
+];
+
+// Tilemap colliders are 2D only
+#[cfg(feature = "3d")]
+fn main() {
+    eprintln!("This example is 2D only, run it without the `3d` feature");
+}
+
+#[cfg(not(feature = "3d"))]
+fn main() {
+    App::new()
+        .insert_resource(ClearColor(Color::srgb(0.8, 0.8, 0.9)))
+        .insert_resource(Gravity::default())
+        .add_plugins(DefaultPlugins)
+        .add_plugins(XPBDPlugin)
+        .add_systems(Startup, startup)
+        .run();
+}
+
+#[cfg(not(feature = "3d"))]
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let blue = materials.add(Color::srgb(0.4, 0.4, 0.6));
+    let red = materials.add(Color::srgb(0.8, 0.3, 0.3));
+
+    let tile_size = 25.;
+    let solid = LEVEL.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+    let builder = TilemapColliderBuilder::from_grid(LEVEL[0].len(), LEVEL.len(), solid)
+        .with_tile_size(tile_size)
+        .with_origin(vector_xy(-350., 150.));
+
+    // The solid tiles merge into a handful of boxes, give each one a mesh
+    let tiles = builder.spawn(&mut commands);
+    info!("{} tiles merged into {} boxes", LEVEL.concat().matches('#').count(), tiles.len());
+    for (tile, rect) in tiles.into_iter().zip(builder.rects()) {
+        let size = vector_xy(rect.columns as Scalar, rect.rows as Scalar) * tile_size;
+        commands.entity(tile).insert((Mesh2d(meshes.add(Rectangle::new(to_f32(size.x), to_f32(size.y)))), MeshMaterial2d(blue.clone())));
+    }
+
+    let radius = 8.;
+    let ball = meshes.add(Circle::new(to_f32(radius)));
+    for i in 0..60 {
+        let pos = vector_xy((i % 15) as Scalar * 40. - 290., 200. + (i / 15) as Scalar * 20.);
+        commands.spawn((
+            Name::new("Ball"),
+            Mesh2d(ball.clone()),
+            MeshMaterial2d(red.clone()),
+            ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., radius),
+            Transform::from_translation(to_translation(pos, 0.)),
+        ));
+    }
+
+    commands.spawn((Name::new("Camera"), Camera2d));
+}
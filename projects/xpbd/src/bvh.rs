@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use bevy::ecs::entity::EntityHashSet;
+use crate::*;
+
+// Axis aligned bounding box
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl Aabb {
+    pub fn from_center(center: Vector, half_extents: Vector) -> Self {
+        Self { min: center - half_extents, max: center + half_extents }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    pub fn center(&self) -> Vector {
+        (self.min + self.max) / 2.
+    }
+}
+
+// Static colliders sorted into a bounding volume hierarchy, so dynamic bodies
+// only test the statics near them instead of every one of them each substep.
+//
+// The tree is rebuilt at the start of a step whenever a static has been added,
+// removed, moved or resized. That makes statics cheap to have by the thousand
+// and still possible to move: write a new `Pos` (or `Transform`) and the static
+// teleports there on the next step. Statics have no velocity, so bodies they
+// land on are pushed out of the way rather than carried along.
+#[derive(Resource, Debug, Default)]
+pub struct StaticBvh {
+    nodes: Vec<BvhNode>,
+    leaves: Vec<(Entity, Aabb)>,
+    // Statics in `leaves`, so removals elsewhere do not cause a rebuild
+    entities: EntityHashSet,
+    pub(crate) rebuilds: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    aabb: Aabb,
+    // Leaves are `leaves[start..start + count]`, inner nodes have their
+    // children at `start` and `start + 1` and a count of 0
+    start: usize,
+    count: usize,
+}
+
+const LEAF_SIZE: usize = 4;
+
+impl StaticBvh {
+    pub fn new(mut leaves: Vec<(Entity, Aabb)>) -> Self {
+        let mut nodes = Vec::new();
+        if !leaves.is_empty() {
+            nodes.push(BvhNode { aabb: Aabb::default(), start: 0, count: leaves.len() });
+            Self::split(&mut nodes, &mut leaves, 0);
+        }
+        let entities = leaves.iter().map(|(entity, _)| *entity).collect();
+        Self { nodes, leaves, entities, rebuilds: 0 }
+    }
+
+    // Median split along the longest axis of the leaf centers
+    fn split(nodes: &mut Vec<BvhNode>, leaves: &mut [(Entity, Aabb)], index: usize) {
+        let BvhNode { start, count, .. } = nodes[index];
+        let items = &mut leaves[start..start + count];
+        nodes[index].aabb = items.iter().skip(1).fold(items[0].1, |aabb, (_, item)| aabb.union(item));
+        if count <= LEAF_SIZE {
+            return;
+        }
+
+        let centers = items.iter().skip(1).fold(Aabb::from_center(items[0].1.center(), Vector::ZERO), |aabb, (_, item)| {
+            aabb.union(&Aabb::from_center(item.center(), Vector::ZERO))
+        });
+        let axis = (centers.max - centers.min).max_position();
+        items.sort_by(|(_, a), (_, b)| a.center()[axis].total_cmp(&b.center()[axis]));
+
+        let children = nodes.len();
+        let half = count / 2;
+        nodes.push(BvhNode { aabb: Aabb::default(), start, count: half });
+        nodes.push(BvhNode { aabb: Aabb::default(), start: start + half, count: count - half });
+        nodes[index].start = children;
+        nodes[index].count = 0;
+        Self::split(nodes, leaves, children);
+        Self::split(nodes, leaves, children + 1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    // Calls `f` with every static whose bounds overlap `aabb`
+    pub fn for_each_intersecting(&self, aabb: &Aabb, mut f: impl FnMut(Entity)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.aabb.intersects(aabb) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start + 1);
+                stack.push(node.start);
+            } else {
+                for (entity, leaf) in &self.leaves[node.start..node.start + node.count] {
+                    if leaf.intersects(aabb) {
+                        f(*entity);
+                    }
+                }
+            }
+        }
+    }
+}
+
+type ChangedStatic = (Without<Mass>, Or<(Changed<Pos>, Changed<CircleCollider>, Changed<BoxCollider>)>);
+
+type StaticShape<'a> = (Entity, &'a Pos, Option<&'a CircleCollider>, Option<&'a BoxCollider>, Option<&'a PhysicsId>);
+
+pub(crate) fn update_static_bvh(
+    changed: Query<(), ChangedStatic>,
+    statics: Query<StaticShape, Without<Mass>>,
+    mut removed_pos: RemovedComponents<Pos>,
+    mut removed_circles: RemovedComponents<CircleCollider>,
+    mut removed_boxes: RemovedComponents<BoxCollider>,
+    mut bvh: ResMut<StaticBvh>,
+    config: Res<SolverConfig>,
+) {
+    // Dynamic bodies come and go all the time, only removing a static matters
+    let removed = removed_pos
+        .read()
+        .chain(removed_circles.read())
+        .chain(removed_boxes.read())
+        .filter(|entity| bvh.entities.contains(entity))
+        .count();
+    if changed.is_empty() && removed == 0 {
+        return;
+    }
+
+    let mut statics: Vec<_> = statics.iter().collect();
+    if config.deterministic {
+        statics.sort_by_key(|(.., id)| id.copied());
+    }
+    let leaves = statics
+        .into_iter()
+        .flat_map(|(entity, pos, circle, box_collider, _)| {
+            let circle = circle.map(|circle| Aabb::from_center(pos.0, Vector::splat(circle.radius)));
+            let box_aabb = box_collider.map(|box_collider| Aabb::from_center(pos.0, box_collider.size / 2.));
+            circle.into_iter().chain(box_aabb).map(move |aabb| (entity, aabb))
+        })
+        .collect();
+
+    let rebuilds = bvh.rebuilds + 1;
+    *bvh = StaticBvh { rebuilds, ..StaticBvh::new(leaves) };
+}
+
+// Bounds of a dynamic circle, to look up the statics it may touch
+pub(crate) fn circle_aabb(pos: Vector, radius: Scalar) -> Aabb {
+    Aabb::from_center(pos, Vector::splat(radius))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn thousands_of_tiles_only_rebuild_when_a_static_moves() {
+        let mut app = test_app();
+        // A floor of 2000 small tiles, and a block well off to the side
+        for i in 0..2000 {
+            let pos = vector_xy(i as Scalar * 10. - 10000., -5.);
+            app.world_mut().spawn(StaticBoxBundle { pos: Pos(pos), collider: BoxCollider { size: box_size(10., 10.) }, ..default() });
+        }
+        let block = app.world_mut().spawn(StaticBoxBundle {
+            pos: Pos(vector_xy(500., 500.)),
+            collider: BoxCollider { size: box_size(40., 40.) },
+            ..default()
+        }).id();
+        let ball = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(3., 50.), Vector::ZERO, 1., 10.)).id();
+
+        step(&mut app, 100);
+        assert_eq!(app.world().resource::<StaticBvh>().len(), 2001);
+        assert_eq!(app.world().resource::<StaticBvh>().rebuilds, 1);
+        let pos = app.world().get::<Pos>(ball).unwrap().0;
+        assert!((pos.y - 10.).abs() < 0.5, "ball at {}", pos);
+
+        // Teleport the block above the ball, where a second ball lands on it
+        app.world_mut().get_mut::<Pos>(block).unwrap().0 = vector_xy(3., 100.);
+        let second = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(3., 200.), Vector::ZERO, 1., 10.)).id();
+        step(&mut app, 100);
+        assert_eq!(app.world().resource::<StaticBvh>().rebuilds, 2);
+        let pos = app.world().get::<Pos>(second).unwrap().0;
+        assert!((pos.y - 130.).abs() < 0.5, "second ball at {}", pos);
+    }
+
+    #[test]
+    fn only_static_changes_rebuild() {
+        let mut app = test_app();
+        let floor = app.world_mut().spawn(StaticBoxBundle {
+            pos: Pos(vector_xy(0., -50.)),
+            collider: BoxCollider { size: box_size(1000., 100.) },
+            ..default()
+        }).id();
+        let rebuilds = |app: &App| app.world().resource::<StaticBvh>().rebuilds;
+
+        // Balls dropping onto the floor and being despawned again
+        for i in 0..10 {
+            let ball = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(i as Scalar * 20., 30.), Vector::ZERO, 1., 10.)).id();
+            step(&mut app, 5);
+            app.world_mut().despawn(ball);
+            step(&mut app, 5);
+        }
+        assert_eq!(rebuilds(&app), 1);
+
+        // Restoring writes the floor back where it already is
+        let snapshot = PhysicsSnapshot::capture(app.world_mut());
+        step(&mut app, 5);
+        snapshot.restore(app.world_mut());
+        step(&mut app, 5);
+        assert_eq!(rebuilds(&app), 1);
+
+        app.world_mut().despawn(floor);
+        step(&mut app, 1);
+        assert_eq!(rebuilds(&app), 2);
+        assert!(app.world().resource::<StaticBvh>().is_empty());
+    }
+}
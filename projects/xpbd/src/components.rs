@@ -1,13 +1,23 @@
 use bevy::prelude::*;
+use crate::*;
 
-#[derive(Component, Debug, Default)]
-pub struct Pos(pub Vec2);
+// World-space position
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+#[require(SyncedTranslation)]
+pub struct Pos(pub Vector);
 
-#[derive(Component, Debug, Default)]
-pub struct PrevPos(pub Vec2);
+// Spawn a body with this to place it where its `Transform` puts it, instead of
+// at the `Pos` it was spawned with. Bodies spawned without a `Pos` get one
+// this way by themselves, this is for bundles that always carry one. Removed
+// once applied.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct PosFromTransform;
 
-#[derive(Component, Debug)]
-pub struct Mass(pub f32);
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct PrevPos(pub Vector);
+
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Mass(pub Scalar);
 
 impl Default for Mass {
     fn default() -> Self {
@@ -15,9 +25,9 @@ impl Default for Mass {
     }
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
 pub struct CircleCollider {
-    pub radius: f32,
+    pub radius: Scalar,
 }
 
 impl Default for CircleCollider {
@@ -26,14 +36,14 @@ impl Default for CircleCollider {
     }
 }
 
-#[derive(Component, Debug, Default)]
-pub struct Vel(pub(crate) Vec2);
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Vel(pub(crate) Vector);
 
-#[derive(Component, Debug, Default)]
-pub struct PreSolveVel(pub(crate) Vec2);
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct PreSolveVel(pub(crate) Vector);
 
-#[derive(Component, Debug)]
-pub struct Restitution(pub f32);
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Restitution(pub Scalar);
 
 impl Default for Restitution {
     fn default() -> Self {
@@ -41,15 +51,105 @@ impl Default for Restitution {
     }
 }
 
-#[derive(Component, Debug)]
+// How two materials touching each other pick the restitution and friction of
+// their contact. Where they disagree, the rule listed last wins.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CombineRule {
+    #[default]
+    Average,
+    Min,
+    Multiply,
+    Max,
+}
+
+impl CombineRule {
+    pub fn combine(self, a: Scalar, b: Scalar) -> Scalar {
+        match self {
+            CombineRule::Average => (a + b) / 2.,
+            CombineRule::Min => a.min(b),
+            CombineRule::Multiply => a * b,
+            CombineRule::Max => a.max(b),
+        }
+    }
+}
+
+// Surface of a body, taking over from its `Restitution`. Bodies without one
+// are frictionless and average their restitution.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct PhysicsMaterial {
+    pub restitution: Scalar,
+    pub friction: Scalar,
+    pub combine: CombineRule,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            restitution: 0.3,
+            friction: 0.5,
+            combine: CombineRule::Average,
+        }
+    }
+}
+
+impl PhysicsMaterial {
+    pub fn new(restitution: Scalar, friction: Scalar) -> Self {
+        Self { restitution, friction, ..default() }
+    }
+
+    pub fn with_combine(self, combine: CombineRule) -> Self {
+        Self { combine, ..self }
+    }
+
+    pub(crate) fn of(restitution: &Restitution, material: Option<&PhysicsMaterial>) -> Self {
+        material.copied().unwrap_or(Self { restitution: restitution.0, friction: 0., combine: CombineRule::Average })
+    }
+
+    // Material of a contact between the two
+    pub fn combine(&self, other: &PhysicsMaterial) -> PhysicsMaterial {
+        let combine = self.combine.max(other.combine);
+        PhysicsMaterial {
+            restitution: combine.combine(self.restitution, other.restitution),
+            friction: combine.combine(self.friction, other.friction),
+            combine,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
 pub struct BoxCollider {
-    pub size: Vec2,
+    pub size: Vector,
 }
 
 impl Default for BoxCollider {
     fn default() -> Self {
         Self {
-            size: Vec2::new(50., 50.),
+            size: Vector::splat(50.),
         }
     }
-}
\ No newline at end of file
+}
+
+// Stable identifier of a body, joint or soft body, preserved across snapshots and runs
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PhysicsId(pub u64);
+
+// Renders the body between fixed steps instead of snapping to `Pos`. Bodies
+// without this component keep having their `Transform` set in `FixedUpdate`.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransformInterpolation {
+    // Blend from `PrevPos` to `Pos`, rendering one step behind the simulation
+    #[default]
+    Interpolate,
+    // Predict ahead of `Pos` using `Vel`, which avoids the lag but can overshoot contacts
+    Extrapolate,
+}
+
+// Translation the physics last wrote into `Transform`, used to tell user edits apart from our own
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct SyncedTranslation(pub(crate) Option<Vec3>);
+
+// In 3D circles are spheres and boxes are cuboids, sizes still being full extents
+#[cfg(feature = "3d")]
+pub type SphereCollider = CircleCollider;
+#[cfg(feature = "3d")]
+pub type CuboidCollider = BoxCollider;
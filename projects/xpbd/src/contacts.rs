@@ -0,0 +1,296 @@
+use bevy::prelude::*;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::platform::collections::HashMap;
+use crate::*;
+
+// Momentum a contact transferred over the step, pushing the bodies apart
+// along the normal and, through friction, across it. Dividing by `DELTA_TIME`
+// gives the force, the Lagrange multiplier over the squared time step of the
+// XPBD paper, as positions here only turn into velocities once per step.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ContactImpulse {
+    pub normal: Scalar,
+    // Applied to the first body of the contact, the second one gets the opposite
+    pub tangent: Vector,
+}
+
+impl ContactImpulse {
+    // Impulse of a position correction of `depth` shared by bodies with these summed inverse masses
+    pub(crate) fn from_correction(depth: Scalar, w_sum: Scalar) -> Self {
+        Self { normal: depth / w_sum / DELTA_TIME, tangent: Vector::ZERO }
+    }
+
+    // Impulse on the first body, the normal pointing away from it
+    pub fn on_first(&self, n: Vector) -> Vector {
+        -n * self.normal + self.tangent
+    }
+}
+
+// Part of a collider a contact is on, which tells contacts of the same two
+// bodies apart from one step to the next
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContactFeature {
+    // Anywhere on a circle, which has no distinct parts
+    #[default]
+    Round,
+    // Box face, facing along `axis` towards its positive or negative end
+    Face { axis: usize, positive: bool },
+    // Box corner, or in 3D also an edge. Bitmasks over the axes, of the ones the
+    // contact lies past the box on and of the ones it does so on the positive side.
+    Corner { outside: u32, positive: u32 },
+}
+
+// Everything known about two bodies touching during the last step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactData {
+    pub entity_a: Entity,
+    pub entity_b: Entity,
+    // Points from `entity_a` towards `entity_b`
+    pub normal: Vector,
+    // Deepest penetration resolved during the step
+    pub depth: Scalar,
+    // Where the bodies touch, relative to each body's position
+    pub local_point_a: Vector,
+    pub local_point_b: Vector,
+    pub impulse: ContactImpulse,
+    pub feature_a: ContactFeature,
+    pub feature_b: ContactFeature,
+}
+
+impl ContactData {
+    // Contact resolved by moving the bodies, which have these summed inverse masses, `depth` apart
+    pub(crate) fn new(entity_a: Entity, entity_b: Entity, normal: Vector, depth: Scalar, w_sum: Scalar) -> Self {
+        Self {
+            entity_a,
+            entity_b,
+            normal,
+            depth,
+            local_point_a: Vector::ZERO,
+            local_point_b: Vector::ZERO,
+            impulse: ContactImpulse::from_correction(depth, w_sum),
+            feature_a: ContactFeature::Round,
+            feature_b: ContactFeature::Round,
+        }
+    }
+
+    pub(crate) fn with_local_points(self, local_point_a: Vector, local_point_b: Vector) -> Self {
+        Self { local_point_a, local_point_b, ..self }
+    }
+
+    pub(crate) fn with_feature_b(self, feature_b: ContactFeature) -> Self {
+        Self { feature_b, ..self }
+    }
+
+    // The same contact seen from the other body
+    pub fn flipped(&self) -> Self {
+        Self {
+            entity_a: self.entity_b,
+            entity_b: self.entity_a,
+            normal: -self.normal,
+            local_point_a: self.local_point_b,
+            local_point_b: self.local_point_a,
+            impulse: ContactImpulse { tangent: -self.impulse.tangent, ..self.impulse },
+            feature_a: self.feature_b,
+            feature_b: self.feature_a,
+            ..*self
+        }
+    }
+
+    // The body `entity` touches, if it is part of this contact
+    pub fn other(&self, entity: Entity) -> Option<Entity> {
+        match entity {
+            _ if entity == self.entity_a => Some(self.entity_b),
+            _ if entity == self.entity_b => Some(self.entity_a),
+            _ => None,
+        }
+    }
+}
+
+// Contacts of a step, at most one per pair of bodies and indexed by body.
+// The substeps resolve a contact again every time the bodies touch, and
+// collision pairs come in both orders, so pushing a contact between bodies
+// that already have one adds its impulse to the existing contact, which keeps
+// the orientation it was first pushed with and takes on the latest normal.
+#[derive(Debug, Default, Clone)]
+pub struct ContactList {
+    contacts: Vec<ContactData>,
+    by_pair: HashMap<(Entity, Entity), usize>,
+    by_entity: EntityHashMap<Vec<usize>>,
+}
+
+impl ContactList {
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ContactData> {
+        self.contacts.iter()
+    }
+
+    // Every contact `entity` is part of, on either side
+    pub fn of(&self, entity: Entity) -> impl Iterator<Item = &ContactData> {
+        self.by_entity
+            .get(&entity)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.contacts[i])
+    }
+
+    // The contact between the two bodies, seen from `entity_a`
+    pub fn between(&self, entity_a: Entity, entity_b: Entity) -> Option<ContactData> {
+        let contact = self.contacts[*self.by_pair.get(&pair_key(entity_a, entity_b))?];
+        Some(if contact.entity_a == entity_a { contact } else { contact.flipped() })
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut ContactData> {
+        self.contacts.iter_mut()
+    }
+
+    pub(crate) fn push(&mut self, contact: ContactData) {
+        let key = pair_key(contact.entity_a, contact.entity_b);
+        if let Some(&i) = self.by_pair.get(&key) {
+            let existing = &mut self.contacts[i];
+            let contact = if existing.entity_a == contact.entity_a { contact } else { contact.flipped() };
+            *existing = ContactData {
+                depth: existing.depth.max(contact.depth),
+                impulse: ContactImpulse {
+                    normal: existing.impulse.normal + contact.impulse.normal,
+                    tangent: existing.impulse.tangent + contact.impulse.tangent,
+                },
+                ..contact
+            };
+            return;
+        }
+
+        let i = self.contacts.len();
+        self.by_pair.insert(key, i);
+        self.by_entity.entry(contact.entity_a).or_default().push(i);
+        self.by_entity.entry(contact.entity_b).or_default().push(i);
+        self.contacts.push(contact);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.contacts.clear();
+        self.by_pair.clear();
+        self.by_entity.clear();
+    }
+}
+
+impl Extend<ContactData> for ContactList {
+    fn extend<T: IntoIterator<Item = ContactData>>(&mut self, contacts: T) {
+        for contact in contacts {
+            self.push(contact);
+        }
+    }
+}
+
+fn pair_key(entity_a: Entity, entity_b: Entity) -> (Entity, Entity) {
+    (entity_a.min(entity_b), entity_a.max(entity_b))
+}
+
+// Sent every step for every contact, after the velocity solve
+#[derive(Message, Debug, Clone, Copy)]
+pub struct Collision(pub ContactData);
+
+// Contact forces on a body over the last step. Only bodies spawned with this
+// component have it filled in.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct ContactForces {
+    // Sum of all contact forces, friction included
+    pub force: Vector,
+    // Normal force of the hardest pressing contact, to break things with
+    pub max_normal_force: Scalar,
+}
+
+pub(crate) fn report_contacts(
+    contacts: Res<Contacts>,
+    static_contacts: Res<StaticContacts>,
+    mut forces: Query<&mut ContactForces>,
+    mut collisions: MessageWriter<Collision>,
+) {
+    for mut contact_forces in forces.iter_mut() {
+        *contact_forces = ContactForces::default();
+    }
+
+    for contact in contacts.iter().chain(static_contacts.iter()) {
+        collisions.write(Collision(*contact));
+
+        let force = contact.impulse.on_first(contact.normal) / DELTA_TIME;
+        let normal_force = contact.impulse.normal / DELTA_TIME;
+        for (entity, force) in [(contact.entity_a, force), (contact.entity_b, -force)] {
+            if let Ok(mut contact_forces) = forces.get_mut(entity) {
+                contact_forces.force += force;
+                contact_forces.max_normal_force = contact_forces.max_normal_force.max(normal_force);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn contacts_report_resting_force_and_impact_impulse() {
+        let mut app = test_app();
+        app.world_mut().spawn(StaticBoxBundle { pos: Pos(vector_xy(0., -50.)), collider: BoxCollider { size: box_size(1000., 100.) }, ..default() });
+        let mut ball = |x: Scalar, y: Scalar| {
+            app.world_mut().spawn((ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(x, y), Vector::ZERO, 2., 10.), ContactForces::default())).id()
+        };
+        let low = ball(-200., 30.);
+        let high = ball(200., 190.);
+
+        // Hardest hit of each ball while it falls and lands
+        let mut hardest = [0.; 2];
+        for _ in 0..80 {
+            step(&mut app, 1);
+            for collision in app.world_mut().resource_mut::<Messages<Collision>>().drain() {
+                for (hardest, ball) in hardest.iter_mut().zip([low, high]) {
+                    if collision.0.entity_a == ball {
+                        *hardest = collision.0.impulse.normal.max(*hardest);
+                    }
+                }
+            }
+        }
+        // Mass times landing speed, plus the 30% bounce back
+        for (hardest, drop) in hardest.into_iter().zip([20., 180.]) {
+            let expected = 2. * (2. * 500. * drop as Scalar).sqrt() * 1.3;
+            assert!((hardest - expected).abs() < expected * 0.05, "impulse {} instead of {}", hardest, expected);
+        }
+
+        // Resting, the floor carries the ball's weight
+        step(&mut app, 200);
+        let forces = app.world().get::<ContactForces>(low).unwrap();
+        assert!(forces.force.distance(vector_xy(0., 1000.)) < 10., "resting force {}", forces.force);
+        assert!((forces.max_normal_force - 1000.).abs() < 10., "normal force {}", forces.max_normal_force);
+    }
+
+    #[test]
+    fn contacts_are_looked_up_by_body_and_pair() {
+        let mut app = test_app();
+        let floor = app.world_mut().spawn(StaticBoxBundle { pos: Pos(vector_xy(0., -50.)), collider: BoxCollider { size: box_size(300., 100.) }, ..default() }).id();
+        let bottom = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 10.), Vector::ZERO, 1., 10.)).id();
+        let top = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 30.), Vector::ZERO, 1., 10.)).id();
+        step(&mut app, 100);
+
+        let contacts = app.world().resource::<Contacts>();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts.of(top).count(), 1);
+        let contact = contacts.between(top, bottom).unwrap();
+        assert!(contact.normal.distance(vector_xy(0., -1.)) < 1e-3, "normal {}", contact.normal);
+        assert!(contact.local_point_a.distance(vector_xy(0., -10.)) < 1e-3);
+        assert_eq!(contacts.between(bottom, top).unwrap().normal, -contact.normal);
+
+        let static_contacts = app.world().resource::<StaticContacts>();
+        assert!(static_contacts.of(top).next().is_none());
+        let contact = static_contacts.between(bottom, floor).unwrap();
+        assert_eq!(contact.other(bottom), Some(floor));
+        assert_eq!(contact.feature_b, ContactFeature::Face { axis: 1, positive: true });
+        assert!((contact.local_point_b.y - 50.).abs() < 0.5, "touching the floor at {}", contact.local_point_b);
+    }
+}
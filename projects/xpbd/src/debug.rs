@@ -0,0 +1,212 @@
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+use crate::*;
+
+// Draws the physics state with gizmos and binds `PhysicsTime` to the keyboard:
+// P pauses, N runs a single step, [ and ] halve and double the time scale.
+// Needs the gizmo and input plugins from `DefaultPlugins`.
+#[derive(Debug, Default)]
+pub struct XPBDDebugPlugin;
+
+impl Plugin for XPBDDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<DebugRenderConfig>()
+            .add_systems(Update, (
+                draw_colliders,
+                draw_aabbs,
+                draw_collision_pairs,
+                draw_contacts,
+                draw_velocities,
+                draw_grab,
+                control_physics_time
+            ));
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct DebugRenderConfig {
+    pub colliders: bool,
+    pub contacts: bool,
+    pub velocities: bool,
+    pub aabbs: bool,
+    pub collision_pairs: bool,
+    // Velocities are drawn as the distance travelled in this many seconds
+    pub velocity_scale: f32,
+}
+
+impl Default for DebugRenderConfig {
+    fn default() -> Self {
+        Self {
+            colliders: true,
+            contacts: true,
+            velocities: true,
+            aabbs: false,
+            collision_pairs: false,
+            velocity_scale: 0.25,
+        }
+    }
+}
+
+fn draw_colliders(
+    mut gizmos: Gizmos,
+    circles: Query<(&Pos, &CircleCollider, Has<Mass>)>,
+    boxes: Query<(&Pos, &BoxCollider)>,
+    config: Res<DebugRenderConfig>,
+) {
+    if !config.colliders {
+        return;
+    }
+
+    for (pos, circle, dynamic) in circles.iter() {
+        let color = if dynamic { css::LIME } else { css::ORANGE };
+        gizmos.circle_2d(Isometry2d::from_translation(to_render(pos.0)), to_f32(circle.radius), color);
+    }
+    for (pos, box_collider) in boxes.iter() {
+        gizmos.rect_2d(Isometry2d::from_translation(to_render(pos.0)), to_render(box_collider.size), css::ORANGE);
+    }
+}
+
+// The same bounds `collect_collision_pairs` tests against, including the velocity margin
+fn draw_aabbs(
+    mut gizmos: Gizmos,
+    query: Query<(&Pos, &Vel, &CircleCollider)>,
+    config: Res<DebugRenderConfig>,
+) {
+    if !config.aabbs {
+        return;
+    }
+
+    for (pos, vel, circle) in query.iter() {
+        let half_extent = circle.radius + broad_phase_margin(vel.0);
+        gizmos.rect_2d(Isometry2d::from_translation(to_render(pos.0)), Vec2::splat(to_f32(2. * half_extent)), css::GRAY);
+    }
+}
+
+fn draw_collision_pairs(
+    mut gizmos: Gizmos,
+    query: Query<&Pos>,
+    collision_pairs: Res<CollisionPairs>,
+    config: Res<DebugRenderConfig>,
+) {
+    if !config.collision_pairs {
+        return;
+    }
+
+    for pair in collision_pairs.0.iter() {
+        if let Ok([pos_a, pos_b]) = query.get_many([pair.entity_a, pair.entity_b]) {
+            gizmos.line_2d(to_render(pos_a.0), to_render(pos_b.0), css::YELLOW);
+        }
+    }
+}
+
+fn draw_contacts(
+    mut gizmos: Gizmos,
+    query: Query<&Pos>,
+    contacts: Res<Contacts>,
+    static_contacts: Res<StaticContacts>,
+    config: Res<DebugRenderConfig>,
+) {
+    if !config.contacts {
+        return;
+    }
+
+    let markers = contact_markers(contacts.iter().chain(static_contacts.iter()), |entity| query.get(entity).ok().map(|pos| pos.0));
+    for (point, push) in markers {
+        gizmos.cross_2d(Isometry2d::from_translation(point), 2., css::RED);
+        gizmos.arrow_2d(point, point + push * 10., css::RED);
+    }
+}
+
+// Every contact, dynamic pairs included, is drawn once on its first body: at the
+// point on that body's surface where it touches the other one, with the
+// direction the first body is pushed in, which is against the contact normal
+fn contact_markers<'a>(
+    contacts: impl Iterator<Item = &'a ContactData>,
+    pos: impl Fn(Entity) -> Option<Vector>,
+) -> Vec<(Vec2, Vec2)> {
+    contacts
+        .filter_map(|contact| {
+            let pos_a = pos(contact.entity_a)?;
+            Some((to_render(pos_a + contact.local_point_a), to_render(-contact.normal)))
+        })
+        .collect()
+}
+
+fn draw_velocities(
+    mut gizmos: Gizmos,
+    query: Query<(&Pos, &Vel)>,
+    config: Res<DebugRenderConfig>,
+) {
+    if !config.velocities {
+        return;
+    }
+
+    for (pos, vel) in query.iter() {
+        if vel.0 != Vector::ZERO {
+            let pos = to_render(pos.0);
+            gizmos.arrow_2d(pos, pos + to_render(vel.0) * config.velocity_scale, css::DEEP_SKY_BLUE);
+        }
+    }
+}
+
+// Only does anything with `XPBDGrabPlugin` added
+fn draw_grab(
+    mut gizmos: Gizmos,
+    query: Query<&Pos>,
+    grab: Option<Res<Grab>>,
+) {
+    let Some(grabbed) = grab.and_then(|grab| grab.0) else {
+        return;
+    };
+    if let Ok(pos) = query.get(grabbed.entity) {
+        let target = to_render(grabbed.target);
+        gizmos.line_2d(to_render(pos.0 + grabbed.offset), target, css::WHITE);
+        gizmos.cross_2d(Isometry2d::from_translation(target), 4., css::WHITE);
+    }
+}
+
+fn control_physics_time(keys: Res<ButtonInput<KeyCode>>, mut time: ResMut<PhysicsTime>) {
+    if keys.just_pressed(KeyCode::KeyP) {
+        time.toggle_pause();
+        info!("Physics {}", if time.paused { "paused" } else { "resumed" });
+    }
+    if keys.just_pressed(KeyCode::KeyN) {
+        time.step(1);
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        time.time_scale = (time.time_scale / 2.).max(0.01);
+        info!("Physics time scale {}", time.time_scale);
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        time.time_scale = (time.time_scale * 2.).min(1.);
+        info!("Physics time scale {}", time.time_scale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn dynamic_pair_contact_is_marked_once_where_the_bodies_touch() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let left = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(-9., 0.), Vector::ZERO, 1., 10.)).id();
+        let right = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(9., 0.), Vector::ZERO, 1., 10.)).id();
+
+        step(&mut app, 1);
+
+        let contacts = app.world().resource::<Contacts>();
+        let markers = contact_markers(contacts.iter(), |entity| app.world().get::<Pos>(entity).map(|pos| pos.0));
+        assert_eq!(markers.len(), 1);
+        let (point, push) = markers[0];
+        assert!(point.length() < 0.5, "marked at {}", point);
+        // Pushed away from the other body
+        let first = if contacts.iter().next().unwrap().entity_a == left { left } else { right };
+        let other = if first == left { right } else { left };
+        let away = app.world().get::<Pos>(first).unwrap().0 - app.world().get::<Pos>(other).unwrap().0;
+        assert!(push.dot(to_render(away).normalize()) > 0.99, "pushed along {}", push);
+    }
+}
@@ -13,7 +13,7 @@ pub struct ParticleBundle {
 }
 
 impl ParticleBundle {
-    pub fn new_with_pos_and_vel(pos: Vec2, vel: Vec2) -> Self {
+    pub fn new_with_pos_and_vel(pos: Vector, vel: Vector) -> Self {
         Self {
             pos: Pos(pos),
             prev_pos: PrevPos(pos - vel * DELTA_TIME),
@@ -22,7 +22,7 @@ impl ParticleBundle {
         }
     }
 
-    pub fn new_with_pos_vel_mass(pos: Vec2, vel: Vec2, mass: f32) -> Self {
+    pub fn new_with_pos_vel_mass(pos: Vector, vel: Vector, mass: Scalar) -> Self {
         Self {
             pos: Pos(pos),
             prev_pos: PrevPos(pos - vel * DELTA_TIME),
@@ -32,7 +32,7 @@ impl ParticleBundle {
         }
     }
 
-    pub fn new_with_pos_vel_mass_radius(pos: Vec2, vel: Vec2, mass: f32, radius: f32) -> Self {
+    pub fn new_with_pos_vel_mass_radius(pos: Vector, vel: Vector, mass: Scalar, radius: Scalar) -> Self {
         Self {
             pos: Pos(pos),
             prev_pos: PrevPos(pos - vel * DELTA_TIME),
@@ -44,6 +44,8 @@ impl ParticleBundle {
     }
 }
 
+// Statics never move on their own. To move one, write its `Pos` or `Transform`
+// and it teleports there at the start of the next step, see `StaticBvh`.
 #[derive(Bundle, Default)]
 pub struct StaticCircleBundle {
     pub pos: Pos,
@@ -56,4 +58,8 @@ pub struct StaticBoxBundle {
     pub pos: Pos,
     pub collider: BoxCollider,
     pub restitution: Restitution,
-}
\ No newline at end of file
+}
+#[cfg(feature = "3d")]
+pub type StaticSphereBundle = StaticCircleBundle;
+#[cfg(feature = "3d")]
+pub type StaticCuboidBundle = StaticBoxBundle;
@@ -0,0 +1,288 @@
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use crate::math::consts::PI;
+use crate::*;
+
+// Position based fluids (Macklin and Müller, 2013). Particles marked with
+// `FluidParticle` keep their ordinary particle components, but instead of
+// colliding with each other they are pushed around by a density constraint
+// solved every substep. They still collide with statics and with all other
+// dynamic bodies through the usual passes, so `StaticBoxBundle`s make containers.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct FluidParticle;
+
+#[derive(Resource, Debug, Clone)]
+pub struct FluidConfig {
+    // Smoothing kernel radius, only particles closer than this interact
+    pub kernel_radius: Scalar,
+    pub rest_density: Scalar,
+    // Softens the density constraint so sparse particles do not blow apart
+    pub relaxation: Scalar,
+    // Artificial pressure that keeps particles from clumping at the surface
+    pub tensile_strength: Scalar,
+    pub tensile_exponent: i32,
+    // Distance, as a fraction of the kernel radius, at which the artificial pressure is `tensile_strength`
+    pub tensile_distance: Scalar,
+    // XSPH viscosity, 0 is inviscid and 1 moves every particle with its neighbours
+    pub viscosity: Scalar,
+}
+
+impl FluidConfig {
+    // Settings for particles of this radius and mass, at rest when packed in a
+    // square (in 3D, cubic) lattice with their colliders touching
+    pub fn new(particle_radius: Scalar, particle_mass: Scalar) -> Self {
+        let h = 4. * particle_radius;
+        let spacing = 2. * particle_radius;
+        let reach = (h / spacing).ceil() as i32;
+        let lattice: Vec<Vector> = cells_within(reach)
+            .map(|cell| from_cell(cell) * spacing)
+            .filter(|r| r.length() < h)
+            .collect();
+
+        let rest_density: Scalar = lattice.iter().map(|r| particle_mass * poly6(r.length_squared(), h)).sum();
+        // Sum of the squared constraint gradients for a particle in the lattice,
+        // which sets the scale of the multipliers
+        let gradient = |r: Vector| particle_mass * spiky_gradient(r, h) / rest_density;
+        let own: Vector = lattice.iter().map(|r| gradient(*r)).sum();
+        let gradients = own.length_squared() + lattice.iter().map(|r| gradient(*r).length_squared()).sum::<Scalar>();
+
+        Self {
+            kernel_radius: h,
+            rest_density,
+            relaxation: 0.1 * gradients,
+            tensile_strength: 0.1 / gradients,
+            tensile_exponent: 4,
+            tensile_distance: 0.2,
+            viscosity: 0.01,
+        }
+    }
+}
+
+impl Default for FluidConfig {
+    fn default() -> Self {
+        Self::new(2.5, 1.)
+    }
+}
+
+// Fluid particles and the indices of their neighbours, found once per step
+#[derive(Resource, Debug, Default)]
+pub struct FluidNeighbours {
+    pub particles: Vec<Entity>,
+    pub neighbours: Vec<Vec<usize>>,
+}
+
+// Kernel normalisation constants differ between 2D and 3D
+#[cfg(not(feature = "3d"))]
+fn poly6_scale(h: Scalar) -> Scalar {
+    4. / (PI * h.powi(8))
+}
+
+#[cfg(feature = "3d")]
+fn poly6_scale(h: Scalar) -> Scalar {
+    315. / (64. * PI * h.powi(9))
+}
+
+#[cfg(not(feature = "3d"))]
+fn spiky_gradient_scale(h: Scalar) -> Scalar {
+    -30. / (PI * h.powi(5))
+}
+
+#[cfg(feature = "3d")]
+fn spiky_gradient_scale(h: Scalar) -> Scalar {
+    -45. / (PI * h.powi(6))
+}
+
+fn poly6(r_sqr: Scalar, h: Scalar) -> Scalar {
+    let h_sqr = h * h;
+    if r_sqr >= h_sqr {
+        return 0.;
+    }
+    poly6_scale(h) * (h_sqr - r_sqr).powi(3)
+}
+
+fn spiky_gradient(r: Vector, h: Scalar) -> Vector {
+    let length = r.length();
+    if length >= h || length <= Scalar::EPSILON {
+        return Vector::ZERO;
+    }
+    spiky_gradient_scale(h) * (h - length).powi(2) * r / length
+}
+
+// Buckets the fluid particles in a grid of kernel sized cells, so only the
+// surrounding cells need checking. Bodies only move a fraction of the
+// kernel radius per step, so this is done once, right after integration.
+pub(crate) fn find_fluid_neighbours(
+    query: Query<(Entity, &Pos), With<FluidParticle>>,
+    ids: Query<&PhysicsId>,
+    mut neighbours: ResMut<FluidNeighbours>,
+    fluid: Res<FluidConfig>,
+    solver: Res<SolverConfig>,
+) {
+    let mut particles: Vec<(Entity, Vector)> = query.iter().map(|(entity, pos)| (entity, pos.0)).collect();
+    if solver.deterministic {
+        particles.sort_by_key(|(entity, _)| ids.get(*entity).ok().copied());
+    }
+
+    let h = fluid.kernel_radius;
+    let cell = |pos: Vector| to_cell(pos / h);
+    let mut grid: HashMap<Cell, Vec<usize>> = HashMap::default();
+    for (i, (_, pos)) in particles.iter().enumerate() {
+        grid.entry(cell(*pos)).or_default().push(i);
+    }
+
+    neighbours.neighbours = particles
+        .iter()
+        .enumerate()
+        .map(|(i, (_, pos))| {
+            neighbourhood(cell(*pos))
+                .filter_map(|cell| grid.get(&cell))
+                .flatten()
+                .copied()
+                .filter(|j| *j != i && particles[*j].1.distance_squared(*pos) < h * h)
+                .collect()
+        })
+        .collect();
+    neighbours.particles = particles.into_iter().map(|(entity, _)| entity).collect();
+}
+
+pub(crate) fn solve_fluid_density(
+    mut query: Query<(&mut Pos, &Mass), With<FluidParticle>>,
+    neighbours: Res<FluidNeighbours>,
+    config: Res<FluidConfig>,
+) {
+    let Some(bodies) = fluid_bodies(&query, &neighbours) else {
+        return;
+    };
+    let h = config.kernel_radius;
+    let rho_0 = config.rest_density;
+    let tensile_reference = poly6((config.tensile_distance * h).powi(2), h);
+
+    let lambdas: Vec<Scalar> = neighbours.neighbours
+        .iter()
+        .enumerate()
+        .map(|(i, neighbours)| {
+            let (pos_i, mass_i) = bodies[i];
+            let mut density = mass_i * poly6(0., h);
+            let mut own_gradient = Vector::ZERO;
+            let mut gradients = 0.;
+            for j in neighbours.iter().copied() {
+                let (pos_j, mass_j) = bodies[j];
+                let r = pos_i - pos_j;
+                density += mass_j * poly6(r.length_squared(), h);
+                let gradient = mass_j * spiky_gradient(r, h) / rho_0;
+                own_gradient += gradient;
+                gradients += gradient.length_squared();
+            }
+            // Only ever push apart, a sparse surface should not pull particles together
+            let c = (density / rho_0 - 1.).max(0.);
+            -c / (own_gradient.length_squared() + gradients + config.relaxation)
+        })
+        .collect();
+
+    for (i, neighbours_i) in neighbours.neighbours.iter().enumerate() {
+        let (pos_i, _) = bodies[i];
+        let mut delta = Vector::ZERO;
+        for j in neighbours_i.iter().copied() {
+            let (pos_j, mass_j) = bodies[j];
+            let r = pos_i - pos_j;
+            let s_corr = -config.tensile_strength * (poly6(r.length_squared(), h) / tensile_reference).powi(config.tensile_exponent);
+            delta += mass_j * (lambdas[i] + lambdas[j] + s_corr) * spiky_gradient(r, h);
+        }
+        if let Ok((mut pos, _)) = query.get_mut(neighbours.particles[i]) {
+            pos.0 += delta / rho_0;
+        }
+    }
+}
+
+// Blends every particle's velocity towards its neighbours'
+pub(crate) fn apply_fluid_viscosity(
+    mut query: Query<(&Pos, &mut Vel, &Mass), With<FluidParticle>>,
+    neighbours: Res<FluidNeighbours>,
+    config: Res<FluidConfig>,
+) {
+    if config.viscosity == 0. {
+        return;
+    }
+    let bodies: Vec<Option<(Vector, Vector, Scalar)>> = neighbours.particles
+        .iter()
+        .map(|entity| query.get(*entity).ok().map(|(pos, vel, mass)| (pos.0, vel.0, mass.0)))
+        .collect();
+
+    let h = config.kernel_radius;
+    let velocities: Vec<Vector> = neighbours.neighbours
+        .iter()
+        .enumerate()
+        .map(|(i, neighbours)| {
+            let Some((pos_i, vel_i, _)) = bodies[i] else {
+                return Vector::ZERO;
+            };
+            let blend: Vector = neighbours
+                .iter()
+                .filter_map(|j| bodies[*j])
+                .map(|(pos_j, vel_j, mass_j)| (vel_j - vel_i) * mass_j / config.rest_density * poly6(pos_i.distance_squared(pos_j), h))
+                .sum();
+            vel_i + config.viscosity * blend
+        })
+        .collect();
+
+    for (entity, velocity) in neighbours.particles.iter().zip(velocities) {
+        if let Ok((_, mut vel, _)) = query.get_mut(*entity) {
+            vel.0 = velocity;
+        }
+    }
+}
+
+// Current position and mass of every particle in `neighbours`, or `None` if
+// one of them has been despawned since the neighbours were found
+fn fluid_bodies(
+    query: &Query<(&mut Pos, &Mass), With<FluidParticle>>,
+    neighbours: &FluidNeighbours,
+) -> Option<Vec<(Vector, Scalar)>> {
+    if neighbours.particles.is_empty() {
+        return None;
+    }
+    neighbours.particles
+        .iter()
+        .map(|entity| query.get(*entity).ok().map(|(pos, mass)| (pos.0, mass.0)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn fluid_column_spreads_over_the_container_floor() {
+        let mut app = test_app();
+        app.insert_resource(FluidConfig::new(2.5, 1.));
+        for (pos, size) in [
+            (vector_xy(0., -10.), box_size(200., 20.)),
+            (vector_xy(-110., 100.), box_size(20., 240.)),
+            (vector_xy(110., 100.), box_size(20., 240.)),
+        ] {
+            app.world_mut().spawn(StaticBoxBundle { pos: Pos(pos), collider: BoxCollider { size }, ..default() });
+        }
+        // A 10 by 20 column of touching particles standing on the floor
+        let particles: Vec<Entity> = (0..200)
+            .map(|i| {
+                let pos = vector_xy((i % 10) as Scalar * 5. - 22.5, (i / 10) as Scalar * 5. + 2.5);
+                app.world_mut().spawn((
+                    FluidParticle,
+                    ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., 2.5),
+                )).id()
+            })
+            .collect();
+
+        step(&mut app, 200);
+
+        let positions = positions(&app, &particles);
+        // The column started with its particles 50 high on average
+        let mean_height = positions.iter().map(|pos| pos.y).sum::<Scalar>() / positions.len() as Scalar;
+        let width = positions.iter().map(|pos| pos.x).fold(Scalar::MIN, Scalar::max)
+            - positions.iter().map(|pos| pos.x).fold(Scalar::MAX, Scalar::min);
+        assert!(positions.iter().all(|pos| pos.is_finite() && pos.x.abs() < 100. && pos.y > 0.), "escaped {:?}", positions);
+        assert!(mean_height < 20., "mean height {}", mean_height);
+        assert!(width > 150., "width {}", width);
+    }
+}
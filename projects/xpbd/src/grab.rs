@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use crate::*;
+
+// Drag bodies around with the mouse. Pressing `GrabConfig::button` picks the
+// collider under the cursor, which then follows the cursor through a soft
+// XPBD attachment until the button is released. Velocity is derived from
+// positions, so a released body keeps the velocity it was dragged with.
+// Needs the input and window plugins from `DefaultPlugins` and a `Camera2d`.
+#[derive(Debug, Default)]
+pub struct XPBDGrabPlugin;
+
+impl Plugin for XPBDGrabPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<GrabConfig>()
+            .init_resource::<Grab>()
+            .add_systems(Update, grab_with_mouse)
+            .add_systems(FixedUpdate, reset_grab.in_set(PhysicsSet::Integrate))
+            // Right before collisions, so they have the last word on where the body goes
+            .add_systems(SubstepSchedule, solve_grab.after(solve_granular).before(solve_pos));
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct GrabConfig {
+    pub button: MouseButton,
+    // Softness of the attachment, 0 pins the body rigidly to the cursor
+    pub compliance: Scalar,
+}
+
+impl Default for GrabConfig {
+    fn default() -> Self {
+        Self {
+            button: MouseButton::Left,
+            compliance: 0.0001,
+        }
+    }
+}
+
+// The body currently being dragged, if any
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct Grab(pub Option<GrabbedBody>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct GrabbedBody {
+    pub entity: Entity,
+    // Where the body was grabbed, relative to its center
+    pub offset: Vector,
+    // Where the grab point should be, in world space
+    pub target: Vector,
+    pub(crate) lambda: Scalar,
+}
+
+impl GrabbedBody {
+    pub fn new(entity: Entity, offset: Vector, target: Vector) -> Self {
+        Self { entity, offset, target, lambda: 0. }
+    }
+}
+
+fn grab_with_mouse(
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    circles: Query<(Entity, &Pos, &CircleCollider)>,
+    boxes: Query<(Entity, &Pos, &BoxCollider)>,
+    config: Res<GrabConfig>,
+    mut grab: ResMut<Grab>,
+) {
+    if buttons.just_released(config.button) {
+        grab.0 = None;
+        return;
+    }
+
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = cameras.single() else {
+        return;
+    };
+    let Some(cursor) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
+        .map(from_render)
+    else {
+        return;
+    };
+
+    if let Some(grabbed) = grab.0.as_mut() {
+        grabbed.target = cursor;
+        return;
+    }
+    if !buttons.just_pressed(config.button) {
+        return;
+    }
+
+    // The body whose center is closest to the cursor wins where colliders overlap
+    let circle_hits = circles
+        .iter()
+        .filter(|(_, pos, circle)| cursor.distance(pos.0) <= circle.radius)
+        .map(|(entity, pos, _)| (entity, pos.0));
+    let box_hits = boxes
+        .iter()
+        .filter(|(_, pos, box_collider)| {
+            let d = (cursor - pos.0).abs();
+            d.x <= box_collider.size.x / 2. && d.y <= box_collider.size.y / 2.
+        })
+        .map(|(entity, pos, _)| (entity, pos.0));
+
+    grab.0 = circle_hits
+        .chain(box_hits)
+        .min_by(|(_, a), (_, b)| cursor.distance_squared(*a).total_cmp(&cursor.distance_squared(*b)))
+        .map(|(entity, pos)| GrabbedBody::new(entity, cursor - pos, cursor));
+}
+
+fn reset_grab(mut grab: ResMut<Grab>) {
+    if let Some(grabbed) = grab.0.as_mut() {
+        grabbed.lambda = 0.;
+    }
+}
+
+// Attaches the grab point to the target. Statics have no mass to pull on, so
+// they are moved to the target directly.
+fn solve_grab(
+    mut query: Query<(&mut Pos, Option<&Mass>)>,
+    mut grab: ResMut<Grab>,
+    config: Res<GrabConfig>,
+) {
+    let Some(grabbed) = grab.0.as_mut() else {
+        return;
+    };
+    let Ok((mut pos, mass)) = query.get_mut(grabbed.entity) else {
+        grab.0 = None;
+        return;
+    };
+
+    let target = grabbed.target - grabbed.offset;
+    match mass {
+        Some(mass) => {
+            let (_, correction) = correct(target - pos.0, 0., 1. / mass.0, config.compliance, &mut grabbed.lambda);
+            pos.0 += correction;
+        }
+        // Only a static that actually moved should rebuild the static BVH
+        None => {
+            pos.set_if_neq(Pos(target));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn grabbed_body_follows_the_target_and_keeps_its_drag_velocity() {
+        let mut app = test_app();
+        app.add_plugins(XPBDGrabPlugin);
+        let ball = app.world_mut()
+            .spawn(ParticleBundle::new_with_pos_vel_mass_radius(Vector::ZERO, Vector::ZERO, 10., 10.))
+            .id();
+        app.world_mut().resource_mut::<Grab>().0 = Some(GrabbedBody::new(ball, vector_xy(5., 0.), vector_xy(5., 0.)));
+
+        // Drag to the right at 200 units per second
+        for i in 0..64 {
+            app.world_mut().resource_mut::<Grab>().0.as_mut().unwrap().target.x = 5. + 200. * DELTA_TIME * i as Scalar;
+            step(&mut app, 1);
+        }
+        let pos = app.world().get::<Pos>(ball).unwrap().0;
+        assert!(pos.distance(vector_xy(200. * DELTA_TIME * 63., 0.)) < 2., "pos {:?}", pos);
+
+        app.world_mut().resource_mut::<Grab>().0 = None;
+        step(&mut app, 1);
+        let vel = app.world().get::<Vel>(ball).unwrap().0;
+        assert!((vel.x - 200.).abs() < 20., "vel {:?}", vel);
+    }
+}
@@ -0,0 +1,386 @@
+use bevy::prelude::*;
+use crate::*;
+
+// Turns a particle into a grain of sand or gravel. Touching grains, and grains
+// resting on statics, resist sliding past each other with Coulomb friction
+// applied to positions ("Unified Particle Physics for Real-Time Applications",
+// Macklin et al. 2014). Grains spin, so friction at a contact sets them rolling
+// rather than only slowing them down, and `rolling_resistance` brakes that
+// rolling so heaps keep their slope. Cohesive grains also pull on other grains
+// within `cohesion_distance` of touching, like damp sand.
+//
+// Settings of two touching grains are averaged. Statics count as grains with
+// the other grain's settings. Grains resolve their own penetrations, then their
+// contacts go through the velocity pass, `Collision` messages and `ContactForces`
+// like any other.
+#[derive(Component, Debug, Clone, Copy)]
+#[require(GrainSpin)]
+pub struct Granular {
+    // Friction coefficient below which touching grains stick
+    pub static_friction: Scalar,
+    // Friction coefficient while sliding
+    pub dynamic_friction: Scalar,
+    // Fraction of the gap between nearby grains closed every substep, 0 for dry grains
+    pub cohesion: Scalar,
+    pub cohesion_distance: Scalar,
+    // Rolling friction coefficient, bounding how far touching grains roll on
+    // each other by the contact depth like friction bounds their sliding. 0
+    // lets them roll freely
+    pub rolling_resistance: Scalar,
+}
+
+impl Default for Granular {
+    fn default() -> Self {
+        Self {
+            static_friction: 0.8,
+            dynamic_friction: 0.6,
+            cohesion: 0.,
+            cohesion_distance: 0.,
+            rolling_resistance: 0.75,
+        }
+    }
+}
+
+// How fast a grain spins, counter-clockwise in 2D
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct GrainSpin {
+    // Radians per second
+    pub angular_vel: AngularVector,
+    // How far the grain has turned this step
+    pub(crate) rotation: AngularVector,
+}
+
+// Moment of inertia of a grain over its mass times its radius squared, a disc
+// in 2D and a solid ball in 3D
+#[cfg(not(feature = "3d"))]
+const INERTIA_FACTOR: Scalar = 0.5;
+#[cfg(feature = "3d")]
+const INERTIA_FACTOR: Scalar = 0.4;
+
+impl Granular {
+    fn combine(&self, other: &Granular) -> Granular {
+        Granular {
+            static_friction: (self.static_friction + other.static_friction) / 2.,
+            dynamic_friction: (self.dynamic_friction + other.dynamic_friction) / 2.,
+            cohesion: (self.cohesion + other.cohesion) / 2.,
+            cohesion_distance: (self.cohesion_distance + other.cohesion_distance) / 2.,
+            rolling_resistance: (self.rolling_resistance + other.rolling_resistance) / 2.,
+        }
+    }
+
+    // How much the broad phase widens its search around grains, so resting
+    // grains keep their contacts and cohesive grains find each other
+    pub(crate) fn reach(&self) -> Scalar {
+        if self.cohesion > 0. { self.cohesion_distance.max(1.) } else { 1. }
+    }
+
+    // Tangential correction cancelling some or all of the relative tangential
+    // displacement `dx` of a contact with normal `n` and penetration `depth`
+    fn friction(&self, dx: Vector, n: Vector, depth: Scalar) -> Vector {
+        let tangential = dx - n * dx.dot(n);
+        let length = tangential.length();
+        if length <= Scalar::EPSILON {
+            return Vector::ZERO;
+        }
+
+        if length < self.static_friction * depth {
+            tangential
+        } else {
+            tangential * (self.dynamic_friction * depth / length).min(1.)
+        }
+    }
+}
+
+// A grain's side of a contact: how easily it moves and turns, and how far it
+// has turned and the point touching the other side has moved this step
+struct GrainContact {
+    w: Scalar,
+    w_rot: Scalar,
+    r: Vector,
+    dx: Vector,
+    rotation: AngularVector,
+}
+
+impl GrainContact {
+    fn new(pos: &Pos, prev_pos: &PrevPos, spin: &GrainSpin, mass: &Mass, radius: Scalar, r: Vector) -> Self {
+        let w = 1. / mass.0;
+        Self {
+            w,
+            w_rot: w / (INERTIA_FACTOR * radius * radius),
+            r,
+            dx: pos.0 - prev_pos.0 + rotation_displacement(spin.rotation, r),
+            rotation: spin.rotation,
+        }
+    }
+
+    // Statics neither move nor turn
+    fn fixed(r: Vector) -> Self {
+        Self { w: 0., w_rot: 0., r, dx: Vector::ZERO, rotation: AngularVector::default() }
+    }
+
+    // How easily a push along the contact moves the touching point, by moving
+    // and by turning the grain
+    fn tangent_w(&self) -> Scalar {
+        self.w + self.w_rot * self.r.length_squared()
+    }
+}
+
+// Corrections for one contact between grains `a` and `b`, pushing `b` along `n`
+// and `a` the other way
+struct ContactCorrection {
+    pos_a: Vector,
+    pos_b: Vector,
+    rotation_a: AngularVector,
+    rotation_b: AngularVector,
+    // Friction impulse on `a`, times `DELTA_TIME`
+    friction: Vector,
+}
+
+fn solve_contact(granular: &Granular, a: &GrainContact, b: &GrainContact, n: Vector, depth: Scalar, radius: Scalar) -> ContactCorrection {
+    let w_sum = a.w + b.w;
+    let w_rot_sum = a.w_rot + b.w_rot;
+    let normal = n * depth / w_sum;
+    let correction = |p: Vector, rotation_a: AngularVector, rotation_b: AngularVector| ContactCorrection {
+        pos_a: -(normal + p) * a.w,
+        pos_b: (normal + p) * b.w,
+        rotation_a,
+        rotation_b,
+        friction: -p,
+    };
+
+    // Friction acts on what is left of the relative motion of the touching
+    // points after the penetration is resolved, moving and turning the grains
+    let friction = granular.friction(a.dx - b.dx + n * depth, n, depth);
+    let p = friction / (a.tangent_w() + b.tangent_w());
+    let rotation_a = -torque(a.r, p) * a.w_rot;
+    let rotation_b = torque(b.r, p) * b.w_rot;
+
+    // Like friction, rolling resistance either holds the grains or only slows
+    // down their rolling
+    let rolling = a.rotation + rotation_a - b.rotation - rotation_b;
+    let resisted = clamp_rotation(rolling, granular.rolling_resistance * depth / radius);
+    if resisted != rolling {
+        return correction(p, rotation_a - resisted * a.w_rot / w_rot_sum, rotation_b + resisted * b.w_rot / w_rot_sum);
+    }
+
+    // Held, so the grains stop turning on each other and friction only has
+    // their sliding to work on
+    let turned = a.rotation - b.rotation;
+    let (rotation_a, rotation_b) = (-turned * a.w_rot / w_rot_sum, turned * b.w_rot / w_rot_sum);
+    let dx = a.dx + rotation_displacement(rotation_a, a.r) - b.dx - rotation_displacement(rotation_b, b.r);
+    let p = granular.friction(dx + n * depth, n, depth) / w_sum;
+    correction(p, rotation_a, rotation_b)
+}
+
+type GrainQuery<'a> = (Entity, &'a mut Pos, &'a PrevPos, &'a mut GrainSpin, &'a CircleCollider, &'a Mass, &'a Granular);
+
+// Runs before the collision passes and resolves every contact of a grain
+// itself, so the correction along the normal can bound the friction, as in the
+// paper. Friction shows up in the contacts' tangential impulse.
+pub(crate) fn solve_granular(
+    mut grains: Query<GrainQuery>,
+    circles: Query<(&Pos, &CircleCollider), Without<Mass>>,
+    boxes: Query<(&Pos, &BoxCollider), Without<Mass>>,
+    bvh: Res<StaticBvh>,
+    collision_pairs: Res<CollisionPairs>,
+    mut contacts: ResMut<Contacts>,
+    mut static_contacts: ResMut<StaticContacts>,
+) {
+    // Pairs come in both orders, each is only solved once
+    for CollisionPair { entity_a, entity_b } in collision_pairs.0.iter().copied() {
+        if entity_a > entity_b {
+            continue;
+        }
+        let Ok([a, b]) = grains.get_many_mut([entity_a, entity_b]) else {
+            continue;
+        };
+        let (
+            (_, mut pos_a, prev_pos_a, mut spin_a, circle_a, mass_a, granular_a),
+            (_, mut pos_b, prev_pos_b, mut spin_b, circle_b, mass_b, granular_b)
+        ) = (a, b);
+        let granular = granular_a.combine(granular_b);
+
+        let ab = pos_b.0 - pos_a.0;
+        let distance = ab.length();
+        if distance <= Scalar::EPSILON {
+            continue;
+        }
+        let n = ab / distance;
+        let depth = circle_a.radius + circle_b.radius - distance;
+        let (w_a, w_b) = (1. / mass_a.0, 1. / mass_b.0);
+        let w_sum = w_a + w_b;
+
+        if depth > 0. {
+            let a = GrainContact::new(&pos_a, prev_pos_a, &spin_a, mass_a, circle_a.radius, n * circle_a.radius);
+            let b = GrainContact::new(&pos_b, prev_pos_b, &spin_b, mass_b, circle_b.radius, -n * circle_b.radius);
+            let radius = circle_a.radius * circle_b.radius / (circle_a.radius + circle_b.radius);
+            let correction = solve_contact(&granular, &a, &b, n, depth, radius);
+            pos_a.0 += correction.pos_a;
+            pos_b.0 += correction.pos_b;
+            spin_a.rotation += correction.rotation_a;
+            spin_b.rotation += correction.rotation_b;
+
+            let mut contact = ContactData::new(entity_a, entity_b, n, depth, w_sum)
+                .with_local_points(a.r, b.r);
+            contact.impulse.tangent = correction.friction / DELTA_TIME;
+            contacts.push(contact);
+        } else if -depth < granular.cohesion_distance {
+            // Pull together, closing part of the gap
+            let correction = n * depth * granular.cohesion;
+            pos_a.0 -= correction * w_a / w_sum;
+            pos_b.0 += correction * w_b / w_sum;
+        }
+    }
+
+    for (entity, mut pos, prev_pos, mut spin, circle, mass, granular) in grains.iter_mut() {
+        let solve_static = |pos: &mut Pos, spin: &mut GrainSpin, n: Vector, depth: Scalar| {
+            let r = n * circle.radius;
+            let grain = GrainContact::new(pos, prev_pos, spin, mass, circle.radius, r);
+            let correction = solve_contact(granular, &grain, &GrainContact::fixed(-r), n, depth, circle.radius);
+            pos.0 += correction.pos_a;
+            spin.rotation += correction.rotation_a;
+            correction.friction / DELTA_TIME
+        };
+
+        bvh.for_each_intersecting(&circle_aabb(pos.0, circle.radius), |static_entity| {
+            if let Ok((static_pos, static_circle)) = circles.get(static_entity) {
+                let ab = static_pos.0 - pos.0;
+                let distance = ab.length();
+                let depth = circle.radius + static_circle.radius - distance;
+                if depth > 0. && distance > Scalar::EPSILON {
+                    let n = ab / distance;
+                    let friction = solve_static(&mut pos, &mut spin, n, depth);
+                    let mut contact = ContactData::new(entity, static_entity, n, depth, 1. / mass.0)
+                        .with_local_points(n * circle.radius, -n * static_circle.radius);
+                    contact.impulse.tangent = friction;
+                    static_contacts.push(contact);
+                }
+            }
+            if let Ok((static_pos, box_collider)) = boxes.get(static_entity)
+                && let Some((n, depth, feature)) = box_contact(pos.0, circle.radius, static_pos.0, box_collider)
+            {
+                let point = pos.0 + n * (circle.radius - depth);
+                let friction = solve_static(&mut pos, &mut spin, n, depth);
+                let mut contact = ContactData::new(entity, static_entity, n, depth, 1. / mass.0)
+                    .with_local_points(point - pos.0, point - static_pos.0)
+                    .with_feature_b(feature);
+                contact.impulse.tangent = friction;
+                static_contacts.push(contact);
+            }
+        });
+    }
+}
+
+// Grains keep spinning between steps, like bodies keep moving
+pub(crate) fn integrate_grain_spin(mut query: Query<&mut GrainSpin>) {
+    for mut spin in query.iter_mut() {
+        spin.rotation = spin.angular_vel * DELTA_TIME;
+    }
+}
+
+pub(crate) fn update_grain_spin(mut query: Query<&mut GrainSpin>) {
+    for mut spin in query.iter_mut() {
+        spin.angular_vel = spin.rotation / DELTA_TIME;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    // Lets a block of grains standing on the floor collapse, and returns the
+    // slope of the heap it settles into, as height over half width
+    fn pour(granular: Option<Granular>) -> Scalar {
+        let mut app = test_app();
+        app.world_mut().spawn(StaticBoxBundle {
+            pos: Pos(vector_xy(0., -50.)),
+            collider: BoxCollider { size: box_size(1000., 100.) },
+            ..default()
+        });
+        let grains: Vec<Entity> = (0..100)
+            .map(|i| {
+                // Every other row is nudged sideways, so the block has a reason to topple
+                let (column, row) = (i % 10, i / 10);
+                let pos = vector_xy(column as Scalar * 10. - 45. + (row % 2) as Scalar * 2., row as Scalar * 10. + 5.);
+                let mut grain = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., 5.));
+                if let Some(granular) = granular {
+                    grain.insert(granular);
+                }
+                grain.id()
+            })
+            .collect();
+
+        step(&mut app, 400);
+
+        let positions = positions(&app, &grains);
+        let height = positions.iter().map(|pos| pos.y).fold(Scalar::MIN, Scalar::max);
+        let half_width = positions.iter().map(|pos| pos.x.abs()).fold(Scalar::MIN, Scalar::max);
+        height / half_width
+    }
+
+    #[test]
+    fn grains_form_a_heap_instead_of_flowing_flat() {
+        let frictionless = pour(None);
+        let marbles = pour(Some(Granular { rolling_resistance: 0., ..default() }));
+        let sand = pour(Some(Granular::default()));
+        // Sand rests at around 30 degrees, frictionless grains run off the
+        // floor and grains that roll freely spread out almost as far
+        assert!(sand > 0.5, "sand slope {}", sand);
+        assert!(frictionless < 0.1, "frictionless slope {}", frictionless);
+        assert!(marbles < 0.1, "freely rolling slope {}", marbles);
+    }
+
+    // Speed of a grain pushed along the floor after a while, and how fast the
+    // point of it touching the floor is moving
+    fn roll(rolling_resistance: Scalar) -> (Scalar, Scalar) {
+        let mut app = test_app();
+        app.world_mut().spawn(StaticBoxBundle {
+            pos: Pos(vector_xy(0., -50.)),
+            collider: BoxCollider { size: box_size(4000., 100.) },
+            ..default()
+        });
+        let grain = app.world_mut().spawn((
+            ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 5.), vector_xy(100., 0.), 1., 5.),
+            Granular { rolling_resistance, ..default() },
+        )).id();
+
+        step(&mut app, 120);
+
+        let vel = app.world().get::<Vel>(grain).unwrap().0;
+        let spin = app.world().get::<GrainSpin>(grain).unwrap().angular_vel;
+        (vel.length(), (vel + rotation_displacement(spin, vector_xy(0., -5.))).length())
+    }
+
+    #[test]
+    fn rolling_resistance_stops_a_rolling_grain() {
+        // Friction turns sliding into rolling without slowing it down for good
+        let (speed, slip) = roll(0.);
+        assert!(speed > 50., "free grain rolling at {}", speed);
+        assert!(slip < 1., "free grain slipping at {}", slip);
+
+        let (speed, _) = roll(Granular::default().rolling_resistance);
+        assert!(speed < 1., "resisted grain rolling at {}", speed);
+    }
+
+    #[test]
+    fn resting_grains_report_their_contacts() {
+        let mut app = test_app();
+        let floor = app.world_mut().spawn(StaticBoxBundle {
+            pos: Pos(vector_xy(0., -50.)),
+            collider: BoxCollider { size: box_size(1000., 100.) },
+            ..default()
+        }).id();
+        let [bottom, top] = [5., 15.].map(|y| {
+            app.world_mut()
+                .spawn((ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., y), Vector::ZERO, 1., 5.), Granular::default()))
+                .id()
+        });
+        step(&mut app, 30);
+
+        let contact = app.world().resource::<StaticContacts>().between(bottom, floor).expect("grain on the floor");
+        assert!(contact.impulse.normal > 0., "{:?}", contact);
+        let contact = app.world().resource::<Contacts>().between(bottom, top).expect("grain on a grain");
+        assert!(contact.normal.y > 0.9, "{:?}", contact);
+    }
+}
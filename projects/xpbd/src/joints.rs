@@ -0,0 +1,512 @@
+use bevy::ecs::component::Mutable;
+use bevy::prelude::*;
+#[cfg(not(feature = "3d"))]
+use crate::math::consts::{PI, TAU};
+use crate::*;
+
+// Joints live on their own entities and link two bodies. Either body may be a
+// static (no `Mass`), which makes it an immovable anchor. Bodies are particles
+// without orientation, so every joint acts on the body centers and angles are
+// measured in world space, from +X towards +Y. `RevoluteJoint` is only
+// available in 2D, where an angle is enough to describe a rotation.
+//
+// Compliance is the inverse stiffness: 0 is perfectly rigid, larger values are softer.
+
+// Keeps the bodies `length` apart
+#[derive(Component, Debug, Clone, Copy)]
+pub struct DistanceJoint {
+    pub body_a: Entity,
+    pub body_b: Entity,
+    pub length: Scalar,
+    pub compliance: Scalar,
+    pub(crate) lambda: Scalar,
+}
+
+impl DistanceJoint {
+    pub fn new(body_a: Entity, body_b: Entity, length: Scalar) -> Self {
+        Self { body_a, body_b, length, compliance: 0., lambda: 0. }
+    }
+
+    pub fn with_compliance(self, compliance: Scalar) -> Self {
+        Self { compliance, ..self }
+    }
+}
+
+// Swings `body_b` around `body_a` on an arm of fixed length, like a pendulum or a
+// door hinge, optionally limited to an angle range and driven by a motor
+#[cfg(not(feature = "3d"))]
+#[derive(Component, Debug, Clone, Copy)]
+pub struct RevoluteJoint {
+    pub body_a: Entity,
+    pub body_b: Entity,
+    pub arm_length: Scalar,
+    pub compliance: Scalar,
+    pub limits: Option<(Scalar, Scalar)>,
+    pub motor: Option<JointMotor>,
+    pub(crate) lambda: Scalar,
+    pub(crate) motor_lambda: Scalar,
+}
+
+#[cfg(not(feature = "3d"))]
+#[derive(Debug, Clone, Copy)]
+pub struct JointMotor {
+    // Target angular velocity in radians per second
+    pub velocity: Scalar,
+    // Softer motors give in more easily to loads
+    pub compliance: Scalar,
+}
+
+#[cfg(not(feature = "3d"))]
+impl RevoluteJoint {
+    pub fn new(body_a: Entity, body_b: Entity, arm_length: Scalar) -> Self {
+        Self {
+            body_a,
+            body_b,
+            arm_length,
+            compliance: 0.,
+            limits: None,
+            motor: None,
+            lambda: 0.,
+            motor_lambda: 0.,
+        }
+    }
+
+    pub fn with_compliance(self, compliance: Scalar) -> Self {
+        Self { compliance, ..self }
+    }
+
+    // The range may cross PI, as in `with_limits(PI * 0.75, PI * 1.25)`, but
+    // has to be narrower than a full turn
+    pub fn with_limits(self, min_angle: Scalar, max_angle: Scalar) -> Self {
+        Self { limits: Some((min_angle, max_angle)), ..self }
+    }
+
+    pub fn with_motor(self, velocity: Scalar, compliance: Scalar) -> Self {
+        Self { motor: Some(JointMotor { velocity, compliance }), ..self }
+    }
+}
+
+// Lets `body_b` slide along `axis` through `body_a`, optionally only between
+// `min` and `max` distance along the axis
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PrismaticJoint {
+    pub body_a: Entity,
+    pub body_b: Entity,
+    pub axis: Vector,
+    pub compliance: Scalar,
+    pub limits: Option<(Scalar, Scalar)>,
+    pub(crate) lambda: Scalar,
+}
+
+impl PrismaticJoint {
+    // Panics if `axis` is zero, or not finite
+    pub fn new(body_a: Entity, body_b: Entity, axis: Vector) -> Self {
+        Self {
+            body_a,
+            body_b,
+            axis: axis.try_normalize().expect("Prismatic joint axis should be a non-zero direction"),
+            compliance: 0.,
+            limits: None,
+            lambda: 0.,
+        }
+    }
+
+    pub fn with_compliance(self, compliance: Scalar) -> Self {
+        Self { compliance, ..self }
+    }
+
+    pub fn with_limits(self, min: Scalar, max: Scalar) -> Self {
+        Self { limits: Some((min, max)), ..self }
+    }
+}
+
+// Welds `body_b` at `offset` from `body_a`
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FixedJoint {
+    pub body_a: Entity,
+    pub body_b: Entity,
+    pub offset: Vector,
+    pub compliance: Scalar,
+    pub(crate) lambda: Scalar,
+}
+
+impl FixedJoint {
+    pub fn new(body_a: Entity, body_b: Entity, offset: Vector) -> Self {
+        Self { body_a, body_b, offset, compliance: 0., lambda: 0. }
+    }
+
+    pub fn with_compliance(self, compliance: Scalar) -> Self {
+        Self { compliance, ..self }
+    }
+}
+
+// Despawns the joint once the force holding its bodies together exceeds this
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BreakForce(pub Scalar);
+
+// Sent when a joint with a `BreakForce` breaks, right before it is despawned
+#[derive(Message, Debug, Clone, Copy)]
+pub struct JointBroken {
+    pub joint: Entity,
+    pub body_a: Entity,
+    pub body_b: Entity,
+    pub force: Scalar,
+}
+
+// What a joint gets to see of each of its bodies
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JointBody {
+    pub(crate) pos: Vector,
+    // Position at the start of the step, only the revolute motor needs it so far
+    #[cfg_attr(feature = "3d", allow(dead_code))]
+    pub(crate) prev_pos: Vector,
+    pub(crate) w: Scalar,
+}
+
+pub(crate) trait Joint: Component<Mutability = Mutable> {
+    fn bodies(&self) -> (Entity, Entity);
+
+    fn set_bodies(&mut self, body_a: Entity, body_b: Entity);
+
+    // Position corrections for both bodies for one solver iteration
+    fn solve(&mut self, a: JointBody, b: JointBody) -> (Vector, Vector);
+
+    // Lagrange multiplier accumulated over the current step
+    fn lambda(&self) -> Scalar;
+
+    fn reset(&mut self);
+}
+
+impl Joint for DistanceJoint {
+    fn bodies(&self) -> (Entity, Entity) {
+        (self.body_a, self.body_b)
+    }
+
+    fn set_bodies(&mut self, body_a: Entity, body_b: Entity) {
+        (self.body_a, self.body_b) = (body_a, body_b);
+    }
+
+    fn solve(&mut self, a: JointBody, b: JointBody) -> (Vector, Vector) {
+        let dir = (b.pos - a.pos).normalize_or(Vector::X);
+        let target = a.pos + dir * self.length;
+        correct(target - b.pos, a.w, b.w, self.compliance, &mut self.lambda)
+    }
+
+    fn lambda(&self) -> Scalar {
+        self.lambda
+    }
+
+    fn reset(&mut self) {
+        self.lambda = 0.;
+    }
+}
+
+#[cfg(not(feature = "3d"))]
+impl Joint for RevoluteJoint {
+    fn bodies(&self) -> (Entity, Entity) {
+        (self.body_a, self.body_b)
+    }
+
+    fn set_bodies(&mut self, body_a: Entity, body_b: Entity) {
+        (self.body_a, self.body_b) = (body_a, body_b);
+    }
+
+    fn solve(&mut self, a: JointBody, b: JointBody) -> (Vector, Vector) {
+        let (mut pos_a, mut pos_b) = (a.pos, b.pos);
+        let (mut correction_a, mut correction_b) = (Vector::ZERO, Vector::ZERO);
+
+        // The motor goes first so the arm and limits always get the last word.
+        // It aims one step of rotation past where the arm was at the start of the step.
+        if let Some(motor) = self.motor {
+            let angle = (b.prev_pos - a.prev_pos).to_angle() + motor.velocity * DELTA_TIME;
+            let target = pos_a + Vector::from_angle(angle) * (pos_b - pos_a).length();
+            (correction_a, correction_b) = correct(target - pos_b, a.w, b.w, motor.compliance, &mut self.motor_lambda);
+            pos_a += correction_a;
+            pos_b += correction_b;
+        }
+
+        let mut angle = (pos_b - pos_a).to_angle();
+        if let Some((min_angle, max_angle)) = self.limits {
+            // Taken relative to the middle of the range, so an arm outside it is
+            // pulled back to the nearer limit, even when that means going past PI
+            let middle = (min_angle + max_angle) / 2.;
+            angle = (middle + wrap_angle(angle - middle)).clamp(min_angle, max_angle);
+        }
+        let target = pos_a + Vector::from_angle(angle) * self.arm_length;
+        let (arm_a, arm_b) = correct(target - pos_b, a.w, b.w, self.compliance, &mut self.lambda);
+
+        (correction_a + arm_a, correction_b + arm_b)
+    }
+
+    fn lambda(&self) -> Scalar {
+        self.lambda
+    }
+
+    fn reset(&mut self) {
+        self.lambda = 0.;
+        self.motor_lambda = 0.;
+    }
+}
+
+impl Joint for PrismaticJoint {
+    fn bodies(&self) -> (Entity, Entity) {
+        (self.body_a, self.body_b)
+    }
+
+    fn set_bodies(&mut self, body_a: Entity, body_b: Entity) {
+        (self.body_a, self.body_b) = (body_a, body_b);
+    }
+
+    fn solve(&mut self, a: JointBody, b: JointBody) -> (Vector, Vector) {
+        let mut along = (b.pos - a.pos).dot(self.axis);
+        if let Some((min, max)) = self.limits {
+            along = along.clamp(min, max);
+        }
+        let target = a.pos + self.axis * along;
+        correct(target - b.pos, a.w, b.w, self.compliance, &mut self.lambda)
+    }
+
+    fn lambda(&self) -> Scalar {
+        self.lambda
+    }
+
+    fn reset(&mut self) {
+        self.lambda = 0.;
+    }
+}
+
+impl Joint for FixedJoint {
+    fn bodies(&self) -> (Entity, Entity) {
+        (self.body_a, self.body_b)
+    }
+
+    fn set_bodies(&mut self, body_a: Entity, body_b: Entity) {
+        (self.body_a, self.body_b) = (body_a, body_b);
+    }
+
+    fn solve(&mut self, a: JointBody, b: JointBody) -> (Vector, Vector) {
+        correct(a.pos + self.offset - b.pos, a.w, b.w, self.compliance, &mut self.lambda)
+    }
+
+    fn lambda(&self) -> Scalar {
+        self.lambda
+    }
+
+    fn reset(&mut self) {
+        self.lambda = 0.;
+    }
+}
+
+// The same angle in -PI..=PI, excluding -PI
+#[cfg(not(feature = "3d"))]
+fn wrap_angle(angle: Scalar) -> Scalar {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+// One XPBD iteration moving body B by `error` relative to body A, split by
+// inverse mass and softened by compliance. `lambda` accumulates over the
+// iterations of a step, as in the XPBD paper.
+pub(crate) fn correct(error: Vector, w_a: Scalar, w_b: Scalar, compliance: Scalar, lambda: &mut Scalar) -> (Vector, Vector) {
+    let c = error.length();
+    let w_sum = w_a + w_b;
+    if c <= Scalar::EPSILON || w_sum == 0. {
+        return (Vector::ZERO, Vector::ZERO);
+    }
+
+    let n = error / c;
+    let alpha = compliance / (DELTA_TIME * DELTA_TIME);
+    let delta_lambda = (c - alpha * *lambda) / (w_sum + alpha);
+    *lambda += delta_lambda;
+
+    (-n * delta_lambda * w_a, n * delta_lambda * w_b)
+}
+
+// Statics have neither `Mass` nor `PrevPos`, and never move
+fn joint_body(pos: &Pos, prev_pos: Option<&PrevPos>, mass: Option<&Mass>) -> JointBody {
+    JointBody {
+        pos: pos.0,
+        prev_pos: prev_pos.map_or(pos.0, |prev_pos| prev_pos.0),
+        w: mass.map_or(0., |mass| 1. / mass.0),
+    }
+}
+
+pub(crate) fn solve_joints<J: Joint>(
+    mut joints: Query<(Entity, &mut J)>,
+    mut bodies: Query<(&mut Pos, Option<&PrevPos>, Option<&Mass>)>,
+    ids: Query<&PhysicsId>,
+    config: Res<SolverConfig>,
+) {
+    let mut joints: Vec<_> = joints.iter_mut().collect();
+    if config.deterministic {
+        joints.sort_by_key(|(entity, _)| ids.get(*entity).ok().copied());
+    }
+    for (_, joint) in joints.iter_mut() {
+        let (entity_a, entity_b) = joint.bodies();
+        let Ok([(mut pos_a, prev_pos_a, mass_a), (mut pos_b, prev_pos_b, mass_b)]) = bodies.get_many_mut([entity_a, entity_b]) else {
+            continue;
+        };
+
+        let (correction_a, correction_b) = joint.solve(
+            joint_body(&pos_a, prev_pos_a, mass_a),
+            joint_body(&pos_b, prev_pos_b, mass_b),
+        );
+        // Statics only get zero corrections, leave them untouched so they do not
+        // look moved to the static BVH
+        if correction_a != Vector::ZERO {
+            pos_a.0 += correction_a;
+        }
+        if correction_b != Vector::ZERO {
+            pos_b.0 += correction_b;
+        }
+    }
+}
+
+// The force is the multiplier divided by the squared time step, as in the XPBD paper
+pub(crate) fn break_joints<J: Joint>(
+    joints: Query<(Entity, &J, &BreakForce)>,
+    mut broken: MessageWriter<JointBroken>,
+    mut commands: Commands,
+) {
+    for (entity, joint, break_force) in joints.iter() {
+        let force = joint.lambda().abs() / (DELTA_TIME * DELTA_TIME);
+        if force > break_force.0 {
+            let (body_a, body_b) = joint.bodies();
+            broken.write(JointBroken { joint: entity, body_a, body_b, force });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub(crate) fn reset_joints<J: Joint>(mut joints: Query<&mut J>) {
+    for mut joint in joints.iter_mut() {
+        joint.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    // A static without a collider, so it does not push the bodies around
+    fn anchor(app: &mut App, pos: Vector) -> Entity {
+        app.world_mut().spawn(Pos(pos)).id()
+    }
+
+    fn bob(app: &mut App, pos: Vector) -> Entity {
+        app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., 1.)).id()
+    }
+
+    fn pos(app: &App, entity: Entity) -> Vector {
+        app.world().get::<Pos>(entity).unwrap().0
+    }
+
+    #[test]
+    fn deterministic_joints_do_not_depend_on_spawn_order() {
+        let run = |reversed: bool| {
+            let mut app = test_app();
+            app.insert_resource(SolverConfig { deterministic: true, ..default() });
+            let top = anchor(&mut app, vector_xy(0., 100.));
+            let links: Vec<Entity> = (1..5).map(|i| bob(&mut app, vector_xy(i as Scalar * 20., 100.))).collect();
+            let mut joints: Vec<_> = (0..links.len())
+                .map(|i| {
+                    let body_a = if i == 0 { top } else { links[i - 1] };
+                    (PhysicsId(100 + i as u64), DistanceJoint::new(body_a, links[i], 20.))
+                })
+                .collect();
+            if reversed {
+                joints.reverse();
+            }
+            app.world_mut().spawn_batch(joints);
+
+            step(&mut app, 60);
+            positions(&app, &links)
+        };
+
+        let in_order = run(false);
+        for (a, b) in in_order.iter().zip(run(true).iter()) {
+            assert_eq!(a.to_array().map(Scalar::to_bits), b.to_array().map(Scalar::to_bits));
+        }
+    }
+
+    #[cfg(not(feature = "3d"))]
+    #[test]
+    fn revolute_joint_swings_at_fixed_length_within_limits() {
+        let mut app = test_app();
+        let hinge = anchor(&mut app, vector_xy(0., 100.));
+        let door = bob(&mut app, vector_xy(50., 100.));
+        app.world_mut().spawn(RevoluteJoint::new(hinge, door, 50.).with_limits(-PI / 4., PI / 4.));
+
+        step(&mut app, 120);
+
+        let arm = pos(&app, door) - pos(&app, hinge);
+        assert!((arm.length() - 50.).abs() < 0.1);
+        assert!((arm.to_angle() + PI / 4.).abs() < 0.01, "angle {}", arm.to_angle());
+    }
+
+    #[cfg(not(feature = "3d"))]
+    #[test]
+    fn revolute_limits_can_span_the_negative_x_axis() {
+        let mut app = test_app();
+        let hinge = anchor(&mut app, vector_xy(0., 100.));
+        let door = bob(&mut app, vector_xy(-50., 100.));
+        app.world_mut().spawn(RevoluteJoint::new(hinge, door, 50.).with_limits(PI * 0.75, PI * 1.25));
+
+        step(&mut app, 120);
+
+        // Falls to the lower limit, pointing down and to the left
+        let arm = pos(&app, door) - pos(&app, hinge);
+        assert!((arm.length() - 50.).abs() < 0.1);
+        assert!((arm.to_angle() + PI * 0.75).abs() < 0.01, "angle {}", arm.to_angle());
+    }
+
+    #[cfg(not(feature = "3d"))]
+    #[test]
+    fn revolute_motor_turns_the_arm() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let hub = anchor(&mut app, Vector::ZERO);
+        let arm = bob(&mut app, vector_xy(20., 0.));
+        app.world_mut().spawn(RevoluteJoint::new(hub, arm, 20.).with_motor(PI, 0.));
+
+        step(&mut app, FIXED_TIMESTEP_INTERVAL as usize / 2);
+
+        // Half a second at PI rad/s is a quarter turn
+        let angle = pos(&app, arm).to_angle();
+        assert!((angle - PI / 2.).abs() < 0.05, "angle {}", angle);
+    }
+
+    #[test]
+    fn prismatic_joint_slides_along_axis_until_limit() {
+        let mut app = test_app();
+        let rail = anchor(&mut app, Vector::ZERO);
+        let slider = bob(&mut app, vector_xy(0., 10.));
+        app.world_mut().spawn(PrismaticJoint::new(rail, slider, vector_xy(1., -1.)).with_limits(-30., 30.));
+
+        step(&mut app, 120);
+
+        let offset = pos(&app, slider);
+        assert!((offset.x + offset.y).abs() < 0.01);
+        assert!((offset.dot(vector_xy(1., -1.).normalize()) - 30.).abs() < 0.01);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-zero")]
+    fn prismatic_joint_needs_an_axis() {
+        PrismaticJoint::new(Entity::PLACEHOLDER, Entity::PLACEHOLDER, Vector::ZERO);
+    }
+
+    #[test]
+    fn fixed_joint_welds_bodies_together() {
+        let mut app = test_app();
+        let a = bob(&mut app, vector_xy(0., 0.));
+        let b = bob(&mut app, vector_xy(10., 0.));
+        app.world_mut().spawn(FixedJoint::new(a, b, vector_xy(10., 0.)));
+        app.world_mut().entity_mut(a).get_mut::<Vel>().unwrap().0 = vector_xy(100., 0.);
+
+        step(&mut app, 30);
+
+        assert!((pos(&app, b) - pos(&app, a) - vector_xy(10., 0.)).length() < 0.01);
+        assert!(pos(&app, a).x > 10.);
+    }
+}
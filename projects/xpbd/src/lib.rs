@@ -1,26 +1,114 @@
 use bevy::prelude::*;
 use bevy::ecs::schedule::ScheduleLabel;
 
+mod bvh;
 mod components;
+mod contacts;
+#[cfg(not(feature = "3d"))]
+mod debug;
 mod entity;
+mod fluid;
+#[cfg(not(feature = "3d"))]
+mod grab;
+mod granular;
+mod joints;
+mod math;
+mod parallel;
 mod resources;
+mod rope;
+mod scene;
+mod snapshot;
+#[cfg(not(feature = "3d"))]
+mod soft_body;
+mod stats;
+#[cfg(test)]
+mod test_utils;
+#[cfg(not(feature = "3d"))]
+mod tilemap;
+mod trace;
 
+pub use bvh::*;
 pub use components::*;
+pub use contacts::*;
+#[cfg(not(feature = "3d"))]
+pub use debug::*;
 pub use entity::*;
+pub use fluid::*;
+#[cfg(not(feature = "3d"))]
+pub use grab::*;
+pub use granular::*;
+pub use joints::*;
+pub use math::*;
 pub use resources::*;
+pub use rope::*;
+pub use scene::*;
+pub use snapshot::*;
+#[cfg(not(feature = "3d"))]
+pub use soft_body::*;
+pub use stats::*;
+#[cfg(not(feature = "3d"))]
+pub use tilemap::*;
+pub use trace::*;
+
+use parallel::*;
 
 
 #[derive(Debug, Default)]
 pub struct XPBDPlugin;
 
 pub const FIXED_TIMESTEP_INTERVAL: i32 = 64; // FPS
-pub const DELTA_TIME: f32 = 1. / FIXED_TIMESTEP_INTERVAL as f32;
+pub const DELTA_TIME: Scalar = 1. / FIXED_TIMESTEP_INTERVAL as Scalar;
 
 pub const NUM_SUBSTEPS: i32 = 10;
-pub const SUB_DT: f32 = DELTA_TIME / NUM_SUBSTEPS as f32;
+pub const SUB_DT: Scalar = DELTA_TIME / NUM_SUBSTEPS as Scalar;
+
+// Safety margin multiplier, bigger than 1 to account for sudden acceleration
+const BROAD_PHASE_MARGIN: Scalar = 2.;
+
+// How far past its collider a body is considered by the broad phase
+#[cfg(not(feature = "3d"))]
+pub(crate) fn broad_phase_margin(vel: Vector) -> Scalar {
+    BROAD_PHASE_MARGIN * DELTA_TIME * vel.length()
+}
 
+// Runs `NUM_SUBSTEPS` times per fixed step, during `PhysicsSet::Substep`
 #[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
-struct SubstepSchedule;
+pub struct SubstepSchedule;
+
+// Stages of the physics pipeline in `FixedUpdate`, in the order they run
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PhysicsSet {
+    // Picks up `Transform` edits and resets per-step bookkeeping
+    Prepare,
+    // Fills `CollisionPairs`
+    BroadPhase,
+    // Applies gravity and moves bodies by their velocity
+    Integrate,
+    // Clears the contacts of the previous step and runs `SubstepSchedule`
+    Substep,
+    // Derives velocities from the solved positions and applies restitution
+    Solve,
+    // Writes results out to `Transform`s, stats and traces
+    Sync,
+}
+
+fn tick_physics_time(mut time: ResMut<PhysicsTime>) {
+    // Bookkeeping only, so `apply_time_scale` still runs just on user changes
+    let running = !time.paused || time.pending_steps > 0;
+    time.bypass_change_detection().running = running;
+    if time.paused && time.pending_steps > 0 {
+        time.pending_steps -= 1;
+    }
+}
+
+fn physics_running(time: Res<PhysicsTime>) -> bool {
+    time.running
+}
+
+fn apply_time_scale(time: Res<PhysicsTime>, mut fixed: ResMut<Time<Fixed>>) {
+    let time_scale = time.time_scale.max(0.01);
+    fixed.set_timestep_seconds(to_f64(DELTA_TIME) / time_scale as f64);
+}
 
 fn run_subteps(world: &mut World) {
     for _ in 0..NUM_SUBSTEPS {
@@ -34,36 +122,139 @@ impl Plugin for XPBDPlugin {
         app
             .insert_resource(Time::<Fixed>::from_hz(FIXED_TIMESTEP_INTERVAL.into()))
             .insert_resource(CollisionPairs::default())
+            .insert_resource(CollisionBatches::default())
             .insert_resource(Contacts::default())
             .insert_resource(StaticContacts::default())
+            .init_resource::<SolverConfig>()
+            .init_resource::<NextPhysicsId>()
+            .init_resource::<PhysicsChecksum>()
+            .init_resource::<PhysicsStats>()
+            .init_resource::<PhysicsTime>()
+            .init_resource::<StaticBvh>()
+            .init_resource::<FluidConfig>()
+            .init_resource::<FluidNeighbours>()
+            .add_message::<JointBroken>()
+            .add_message::<Collision>()
+            .add_observer(assign_physics_id::<Pos>)
+            .add_observer(assign_physics_id::<DistanceJoint>)
+            .add_observer(assign_physics_id::<PrismaticJoint>)
+            .add_observer(assign_physics_id::<FixedJoint>)
+            .add_observer(reserve_physics_id)
+            .add_observer(pos_from_transform_if_missing::<Mass>)
+            .add_observer(pos_from_transform_if_missing::<CircleCollider>)
+            .add_observer(pos_from_transform_if_missing::<BoxCollider>)
+            .add_observer(init_pos_from_transform)
             .add_schedule(Schedule::new(SubstepSchedule))
             .add_systems(SubstepSchedule, (
+                solve_joints::<DistanceJoint>,
+                #[cfg(not(feature = "3d"))]
+                solve_joints::<RevoluteJoint>,
+                solve_joints::<PrismaticJoint>,
+                solve_joints::<FixedJoint>,
+                #[cfg(not(feature = "3d"))]
+                solve_soft_bodies,
+                solve_fluid_density,
+                solve_granular,
                 solve_pos,
                 solve_pos_statics,
                 solve_pos_static_boxes
-            ))
+            ).chain())
+            .configure_sets(FixedUpdate, (
+                PhysicsSet::Prepare,
+                PhysicsSet::BroadPhase,
+                PhysicsSet::Integrate,
+                PhysicsSet::Substep,
+                PhysicsSet::Solve,
+                PhysicsSet::Sync
+            ).chain())
+            .configure_sets(FixedUpdate, (
+                PhysicsSet::BroadPhase,
+                PhysicsSet::Integrate,
+                PhysicsSet::Substep,
+                PhysicsSet::Solve
+            ).run_if(physics_running).run_if(not(resource_exists::<TraceReplay>)))
+            .add_systems(PreUpdate, apply_time_scale.run_if(resource_changed::<PhysicsTime>))
+            .add_systems(FixedUpdate, (
+                tick_physics_time,
+                apply_transform_edits,
+                update_static_bvh,
+                start_stats_clock
+            ).chain().in_set(PhysicsSet::Prepare))
             .add_systems(FixedUpdate, (
                 collect_collision_pairs,
+                batch_collision_pairs,
+                lap(|stats| &mut stats.broad_phase_time)
+            ).chain().in_set(PhysicsSet::BroadPhase))
+            .add_systems(FixedUpdate, (
                 integrate,
+                integrate_grain_spin,
+                find_fluid_neighbours,
+                lap(|stats| &mut stats.integrate_time)
+            ).chain().in_set(PhysicsSet::Integrate))
+            .add_systems(FixedUpdate, (
                 clear_contacts,
+                (
+                    reset_joints::<DistanceJoint>,
+                    #[cfg(not(feature = "3d"))]
+                    reset_joints::<RevoluteJoint>,
+                    reset_joints::<PrismaticJoint>,
+                    reset_joints::<FixedJoint>,
+                    #[cfg(not(feature = "3d"))]
+                    reset_soft_bodies
+                ),
                 run_subteps,
+                (
+                    break_joints::<DistanceJoint>,
+                    #[cfg(not(feature = "3d"))]
+                    break_joints::<RevoluteJoint>,
+                    break_joints::<PrismaticJoint>,
+                    break_joints::<FixedJoint>
+                ),
+                lap(|stats| &mut stats.substeps_time)
+            ).chain().in_set(PhysicsSet::Substep))
+            .add_systems(FixedUpdate, (
                 update_vel,
+                update_grain_spin,
+                apply_fluid_viscosity,
                 solve_vel,
                 solve_vel_statics,
+                report_contacts,
+                lap(|stats| &mut stats.velocity_solve_time)
+            ).chain().in_set(PhysicsSet::Solve))
+            .add_systems(FixedUpdate, (
+                replay_trace.run_if(physics_running).run_if(resource_exists::<TraceReplay>),
+                update_stats,
+                update_checksum,
+                record_trace.run_if(physics_running).run_if(resource_exists::<TraceRecorder>),
                 sync_transforms
-            ).chain());
+            ).chain().in_set(PhysicsSet::Sync))
+            .add_systems(PostUpdate, interpolate_transforms.before(TransformSystems::Propagate));
+        #[cfg(not(feature = "3d"))]
+        app.add_observer(assign_physics_id::<RevoluteJoint>)
+            .add_observer(assign_physics_id::<SoftBody>)
+            .add_systems(PostUpdate, update_soft_body_meshes.run_if(resource_exists::<Assets<Mesh>>));
+
+        register_physics_diagnostics(app);
     }
 }
 
 fn collect_collision_pairs(
     query: Query<(Entity, &Pos, &Vel, &CircleCollider)>,
+    ids: Query<&PhysicsId>,
+    fluids: Query<(), With<FluidParticle>>,
+    granular: Query<&Granular>,
     mut collision_pairs: ResMut<CollisionPairs>,
+    config: Res<SolverConfig>,
+    gravity: Res<Gravity>,
 ) {
     collision_pairs.0.clear();
 
-    let k = 2.; // safety margin multiplier, bigger than 1 to account for sudden acceleration
+    let k = BROAD_PHASE_MARGIN;
     let safety_margin_factor = k * DELTA_TIME;
     let safety_margin_factor_sqr = safety_margin_factor * safety_margin_factor;
+    // Bodies resting on each other have no velocity, but gravity pulls the
+    // upper one this far into the lower one during the step
+    let resting_margin = gravity.0.length() * DELTA_TIME * DELTA_TIME;
 
     {
         for (entity_a, pos_a, vel_a, collider_a) in query.iter() {
@@ -72,18 +263,29 @@ fn collect_collision_pairs(
                 if entity_a == entity_b {
                     continue;
                 }
+                // Fluid particles keep apart through their density constraint instead
+                if fluids.contains(entity_a) && fluids.contains(entity_b) {
+                    continue;
+                }
                 let ab = pos_b.0 - pos_a.0;
                 let vel_b_sqr = vel_b.0.length_squared();
                 let safety_margin_sqr = safety_margin_factor_sqr * (vel_a_sqr + vel_b_sqr);
 
-                let combined_radius = collider_a.radius + collider_b.radius + safety_margin_sqr.sqrt();
+                let mut combined_radius = collider_a.radius + collider_b.radius + safety_margin_sqr.sqrt() + resting_margin;
+                if let Ok([granular_a, granular_b]) = granular.get_many([entity_a, entity_b]) {
+                    combined_radius += granular_a.reach().max(granular_b.reach());
+                }
 
                 if ab.length_squared() < combined_radius * combined_radius {
-                    collision_pairs.0.push((entity_a, entity_b));
+                    collision_pairs.0.push(CollisionPair { entity_a, entity_b });
                 }
             }
         }
     }
+
+    if config.deterministic {
+        collision_pairs.0.sort_by_key(|pair| (ids.get(pair.entity_a).ok().copied(), ids.get(pair.entity_b).ok().copied()));
+    }
 }
 
 fn integrate(mut query: Query<(&mut Pos, &mut PrevPos, &mut Vel, &mut PreSolveVel, &Mass)>, gravity: Res<Gravity>) {
@@ -98,100 +300,217 @@ fn integrate(mut query: Query<(&mut Pos, &mut PrevPos, &mut Vel, &mut PreSolveVe
     }
 }
 
+fn batch_collision_pairs(
+    collision_pairs: Res<CollisionPairs>,
+    mut batches: ResMut<CollisionBatches>,
+) {
+    batches.0 = colour_pairs(&collision_pairs.0);
+}
+
 fn solve_pos(
     query: Query<(&mut Pos, &CircleCollider, &Mass)>,
-    collision_pairs: ResMut<CollisionPairs>
+    collision_pairs: Res<CollisionPairs>,
+    batches: Res<CollisionBatches>,
+    mut contacts: ResMut<Contacts>,
+    config: Res<SolverConfig>,
+    mut stats: ResMut<PhysicsStats>,
 ) {
-    for (entity_a, entity_b) in collision_pairs.0.iter() {
-        let (
-            (mut pos_a, circle_a, mass_a),
-            (mut pos_b, circle_b, mass_b)
-        ) = unsafe {
-            assert!(entity_a != entity_b);
-            (
-                query.get_unchecked(*entity_a).unwrap_unchecked(),
-                query.get_unchecked(*entity_b).unwrap_unchecked(),
-            )
-        };
-        let ab = pos_b.0 - pos_a.0;
-        let combined_radius = circle_a.radius + circle_b.radius;
-        let ab_sqr_len = ab.length_squared();
-        if ab_sqr_len < combined_radius * combined_radius {
-            let ab_length = ab_sqr_len.sqrt();
-            let penetration_depth = combined_radius - ab_length;
-            let n = ab / ab_length;
-
-            let w_a = 1. / mass_a.0;
-            let w_b = 1. / mass_b.0;
-            let w_sum = w_a + w_b;
-
-            pos_a.0 -= n * penetration_depth * w_a / w_sum;
-            pos_b.0 += n * penetration_depth * w_b / w_sum;
+    let mut max_penetration: Scalar = 0.;
+
+    if !config.parallel {
+        for pair in collision_pairs.0.iter() {
+            // Safety: pairs are solved one at a time
+            if let Some(contact) = unsafe { solve_pair(&query, pair) } {
+                max_penetration = max_penetration.max(contact.depth);
+                contacts.push(contact);
+            }
+        }
+    } else {
+        for batch in batches.0.iter() {
+            let chunk_size = chunk_size(batch.len(), true);
+            let chunk_results = par_map_chunks(batch.chunks(chunk_size), |chunk| {
+                let mut chunk_contacts = Vec::new();
+                let mut max_penetration: Scalar = 0.;
+                for pair in chunk.iter() {
+                    // Safety: pairs within a batch never share a body
+                    if let Some(contact) = unsafe { solve_pair(&query, pair) } {
+                        max_penetration = max_penetration.max(contact.depth);
+                        chunk_contacts.push(contact);
+                    }
+                }
+                (chunk_contacts, max_penetration)
+            });
+            for (chunk, chunk_penetration) in chunk_results {
+                contacts.extend(chunk);
+                max_penetration = max_penetration.max(chunk_penetration);
+            }
         }
     }
+
+    stats.max_penetration = stats.max_penetration.max(max_penetration);
+}
+
+// Returns the contact that was resolved, if the circles touch.
+// Safety: no other live reference may access the `Pos` of either entity
+unsafe fn solve_pair(
+    query: &Query<(&mut Pos, &CircleCollider, &Mass)>,
+    &CollisionPair { entity_a, entity_b }: &CollisionPair,
+) -> Option<ContactData> {
+    let (
+        (mut pos_a, circle_a, mass_a),
+        (mut pos_b, circle_b, mass_b)
+    ) = unsafe {
+        assert!(entity_a != entity_b);
+        (
+            query.get_unchecked(entity_a).unwrap_unchecked(),
+            query.get_unchecked(entity_b).unwrap_unchecked(),
+        )
+    };
+    let ab = pos_b.0 - pos_a.0;
+    let combined_radius = circle_a.radius + circle_b.radius;
+    let ab_sqr_len = ab.length_squared();
+    if ab_sqr_len < combined_radius * combined_radius {
+        let ab_length = ab_sqr_len.sqrt();
+        let penetration_depth = combined_radius - ab_length;
+        let n = ab / ab_length;
+
+        let w_a = 1. / mass_a.0;
+        let w_b = 1. / mass_b.0;
+        let w_sum = w_a + w_b;
+
+        pos_a.0 -= n * penetration_depth * w_a / w_sum;
+        pos_b.0 += n * penetration_depth * w_b / w_sum;
+        return Some(
+            ContactData::new(entity_a, entity_b, n, penetration_depth, w_sum)
+                .with_local_points(n * circle_a.radius, -n * circle_b.radius)
+        );
+    }
+    None
 }
 
 fn solve_pos_statics(
-    mut dynamics: Query<(Entity, &mut Pos, &CircleCollider), With<Mass>>,
-    statics: Query<(Entity, &Pos, &CircleCollider), Without<Mass>>,
-    mut contacts: ResMut<StaticContacts>
+    mut dynamics: Query<(Entity, &mut Pos, &CircleCollider, &Mass)>,
+    statics: Query<(&Pos, &CircleCollider), Without<Mass>>,
+    bvh: Res<StaticBvh>,
+    ids: Query<&PhysicsId>,
+    mut contacts: ResMut<StaticContacts>,
+    config: Res<SolverConfig>,
+    mut stats: ResMut<PhysicsStats>,
 ) {
-    for (entity_a, mut pos_a, collider_a) in dynamics.iter_mut() {
-        for (entity_b, pos_b, collider_b) in statics.iter() {
-            let ab = pos_b.0 - pos_a.0;
-            let combined_radius = collider_a.radius + collider_b.radius;
-            let ab_sqr_len = ab.length_squared();
-            if ab_sqr_len < combined_radius * combined_radius {
-                let ab_length = ab_sqr_len.sqrt();
-                let penetration_depth = combined_radius - ab_length;
-                let n = ab / ab_length;
-                pos_a.0 -= n * penetration_depth;
-                contacts.0.push((entity_a, entity_b, n));
-            }
+    // Every dynamic body only moves itself here, so they can be split freely across threads
+    let mut dynamics: Vec<_> = dynamics.iter_mut().collect();
+    if config.deterministic {
+        dynamics.sort_by_key(|(entity, ..)| ids.get(*entity).ok().copied());
+    }
+    let chunk_size = chunk_size(dynamics.len(), config.parallel);
+    let chunk_results = par_map_chunks(dynamics.chunks_mut(chunk_size), |chunk| {
+        let mut chunk_contacts = Vec::new();
+        let mut max_penetration: Scalar = 0.;
+        for (entity_a, pos_a, collider_a, mass_a) in chunk.iter_mut() {
+            bvh.for_each_intersecting(&circle_aabb(pos_a.0, collider_a.radius), |entity_b| {
+                let Ok((pos_b, collider_b)) = statics.get(entity_b) else {
+                    return;
+                };
+                let ab = pos_b.0 - pos_a.0;
+                let combined_radius = collider_a.radius + collider_b.radius;
+                let ab_sqr_len = ab.length_squared();
+                if ab_sqr_len < combined_radius * combined_radius {
+                    let ab_length = ab_sqr_len.sqrt();
+                    let penetration_depth = combined_radius - ab_length;
+                    let n = ab / ab_length;
+                    pos_a.0 -= n * penetration_depth;
+                    max_penetration = max_penetration.max(penetration_depth);
+                    chunk_contacts.push(
+                        ContactData::new(*entity_a, entity_b, n, penetration_depth, 1. / mass_a.0)
+                            .with_local_points(n * collider_a.radius, -n * collider_b.radius)
+                    );
+                }
+            });
         }
+        (chunk_contacts, max_penetration)
+    });
+
+    for (chunk, max_penetration) in chunk_results {
+        contacts.0.extend(chunk);
+        stats.max_penetration = stats.max_penetration.max(max_penetration);
     }
 }
 
 fn solve_pos_static_boxes(
-    mut dynamics: Query<(Entity, &mut Pos, &CircleCollider), With<Mass>>,
-    statics: Query<(Entity, &Pos, &BoxCollider), Without<Mass>>,
-    mut contacts: ResMut<StaticContacts>
+    mut dynamics: Query<(Entity, &mut Pos, &CircleCollider, &Mass)>,
+    statics: Query<(&Pos, &BoxCollider), Without<Mass>>,
+    bvh: Res<StaticBvh>,
+    ids: Query<&PhysicsId>,
+    mut contacts: ResMut<StaticContacts>,
+    config: Res<SolverConfig>,
+    mut stats: ResMut<PhysicsStats>,
 ) {
-    for (entity_a, mut pos_a, circle_a) in dynamics.iter_mut() {
-        for (entity_b, pos_b, box_b) in statics.iter() {
-            let box_to_circle = pos_a.0 - pos_b.0;
-            let box_to_circle_abs = box_to_circle.abs();
-            let half_extents = box_b.size / 2.;
-            let corner_to_center = box_to_circle_abs - half_extents;
-            let r = circle_a.radius;
-            if corner_to_center.x > r || corner_to_center.y > r {
-                continue;
-            }
+    let mut dynamics: Vec<_> = dynamics.iter_mut().collect();
+    if config.deterministic {
+        dynamics.sort_by_key(|(entity, ..)| ids.get(*entity).ok().copied());
+    }
+    let chunk_size = chunk_size(dynamics.len(), config.parallel);
+    let chunk_results = par_map_chunks(dynamics.chunks_mut(chunk_size), |chunk| {
+        let mut chunk_contacts = Vec::new();
+        let mut max_penetration: Scalar = 0.;
+        for (entity_a, pos_a, circle_a, mass_a) in chunk.iter_mut() {
+            bvh.for_each_intersecting(&circle_aabb(pos_a.0, circle_a.radius), |entity_b| {
+                let Ok((pos_b, box_b)) = statics.get(entity_b) else {
+                    return;
+                };
+                let Some((n, penetration_depth, feature)) = box_contact(pos_a.0, circle_a.radius, pos_b.0, box_b) else {
+                    return;
+                };
 
-            let s = box_to_circle.signum();
+                // Where the circle reaches deepest into the box, before it is pushed out
+                let point = pos_a.0 + n * (circle_a.radius - penetration_depth);
+                chunk_contacts.push(
+                    ContactData::new(*entity_a, entity_b, n, penetration_depth, 1. / mass_a.0)
+                        .with_local_points(point - pos_a.0, point - pos_b.0)
+                        .with_feature_b(feature)
+                );
+                pos_a.0 -= n * penetration_depth;
+                max_penetration = max_penetration.max(penetration_depth);
+            });
+        }
+        (chunk_contacts, max_penetration)
+    });
 
-            let (n, penetration_depth) = if corner_to_center.x > 0. && corner_to_center.y > 0. {
-                // Corner
-                let corner_to_center_sqr = corner_to_center.length_squared();
-                if corner_to_center_sqr > r * r {
-                    continue;
-                }
+    for (chunk, max_penetration) in chunk_results {
+        contacts.0.extend(chunk);
+        stats.max_penetration = stats.max_penetration.max(max_penetration);
+    }
+}
 
-                let corner_dist = corner_to_center_sqr.sqrt();
-                let penetration_depth = r - corner_dist;
-                let n = box_to_circle / corner_dist * -s;
-                (n, penetration_depth)
-            } else if corner_to_center.x > corner_to_center.y {
-                // Vertical edge
-                (Vec2::X * -s.x, -corner_to_center.x + r)
-            } else {
-                // Horizontal edge
-                (Vec2::Y * -s.y, -corner_to_center.y + r)
-            };
+// Contact normal, pointing from the circle into the box, penetration depth and
+// the part of the box touched, of a circle touching a static box
+pub(crate) fn box_contact(circle_pos: Vector, r: Scalar, box_pos: Vector, box_collider: &BoxCollider) -> Option<(Vector, Scalar, ContactFeature)> {
+    let box_to_circle = circle_pos - box_pos;
+    let half_extents = box_collider.size / 2.;
+    let corner_to_center = box_to_circle.abs() - half_extents;
+    if corner_to_center.max_element() > r {
+        return None;
+    }
+
+    let s = box_to_circle.signum();
 
-            pos_a.0 -= n * penetration_depth;
-            contacts.0.push((entity_a, entity_b, n));
+    let outside = corner_to_center.max(Vector::ZERO);
+    if outside.cmpgt(Vector::ZERO).bitmask().count_ones() > 1 {
+        // Corner, or an edge of a cuboid
+        let corner_dist = outside.length();
+        if corner_dist > r {
+            return None;
         }
+        let feature = ContactFeature::Corner {
+            outside: outside.cmpgt(Vector::ZERO).bitmask(),
+            positive: box_to_circle.cmpgt(Vector::ZERO).bitmask(),
+        };
+        Some((outside / corner_dist * -s, r - corner_dist, feature))
+    } else {
+        // Face, pushing out along the axis the center is closest to leaving the box by
+        let axis = corner_to_center.max_position();
+        let feature = ContactFeature::Face { axis, positive: s[axis] > 0. };
+        Some((Vector::AXES[axis] * -s[axis], r - corner_to_center[axis], feature))
     }
 }
 
@@ -202,59 +521,234 @@ fn update_vel(mut query: Query<(&mut Pos, &mut PrevPos, &mut Vel)>) {
 }
 
 fn solve_vel(
-    query: Query<(&mut Vel, &PreSolveVel, &Mass, &Restitution)>,
-    contacts: Res<Contacts>
+    query: Query<(&mut Vel, &PreSolveVel, &Mass, &Restitution, Option<&PhysicsMaterial>)>,
+    mut contacts: ResMut<Contacts>,
+    config: Res<SolverConfig>,
+    gravity: Res<Gravity>,
 ) {
-    for (entity_a, entity_b, n) in contacts.0.iter().cloned() {
+    let threshold = config.restitution_threshold_speed(&gravity);
+    for contact in contacts.iter_mut() {
         let (
-            (mut vel_a, pre_solve_vel_a, mass_a, restitution_a),
-            (mut vel_b, pre_solve_vel_b, mass_b, restituion_b)
+            (mut vel_a, pre_solve_vel_a, mass_a, restitution_a, material_a),
+            (mut vel_b, pre_solve_vel_b, mass_b, restituion_b, material_b)
         ) = unsafe {
-            assert!(entity_a != entity_b); // Ensure safety
+            assert!(contact.entity_a != contact.entity_b); // Ensure safety
             (
-                query.get_unchecked(entity_a).unwrap(),
-                query.get_unchecked(entity_b).unwrap()
+                query.get_unchecked(contact.entity_a).unwrap(),
+                query.get_unchecked(contact.entity_b).unwrap()
             )
         };
+        let n = contact.normal;
 
         let pre_solve_relative_vel = pre_solve_vel_a.0 - pre_solve_vel_b.0;
-        let pre_solve_normal_vel = Vec2::dot(pre_solve_relative_vel, n);
+        let pre_solve_normal_vel = Vector::dot(pre_solve_relative_vel, n);
 
         let relative_vel = vel_a.0 - vel_b.0;
-        let normal_vel = Vec2::dot(relative_vel, n);
-        let restitution = (restitution_a.0 + restituion_b.0) / 2.;
+        let normal_vel = Vector::dot(relative_vel, n);
+        let material = PhysicsMaterial::of(restitution_a, material_a).combine(&PhysicsMaterial::of(restituion_b, material_b));
+        let restitution = if pre_solve_normal_vel.abs() < threshold { 0. } else { material.restitution };
 
         let w_a = 1. / mass_a.0;
         let w_b = 1. / mass_b.0;
         let w_sum = w_a + w_b;
 
-        vel_a.0 = n * (-normal_vel - restitution * pre_solve_normal_vel) * w_a / w_sum;
-        vel_b.0 = n * (-normal_vel - restitution * pre_solve_normal_vel) * w_b / w_sum;
+        let normal_delta = -normal_vel - restitution * pre_solve_normal_vel;
+        let stopped_speed = pre_solve_normal_vel.max(contact.impulse.normal * w_sum);
+        let friction = friction_delta(relative_vel, pre_solve_relative_vel, n, stopped_speed, material.friction);
+        vel_a.0 += (n * normal_delta + friction) * w_a / w_sum;
+        vel_b.0 -= (n * normal_delta + friction) * w_b / w_sum;
+        contact.impulse.normal -= normal_delta / w_sum;
+        contact.impulse.tangent += friction / w_sum;
     }
 }
 
 fn solve_vel_statics(
-    mut dynamics: Query<(&mut Vel, &PreSolveVel, &Restitution), With<Mass>>,
-    statics: Query<&Restitution, Without<Mass>>,
-    contacts: Res<StaticContacts>
+    mut dynamics: Query<(&mut Vel, &PreSolveVel, &Mass, &Restitution, Option<&PhysicsMaterial>)>,
+    statics: Query<(&Restitution, Option<&PhysicsMaterial>), Without<Mass>>,
+    mut contacts: ResMut<StaticContacts>,
+    config: Res<SolverConfig>,
+    gravity: Res<Gravity>,
+) {
+    let threshold = config.restitution_threshold_speed(&gravity);
+    for contact in contacts.iter_mut() {
+        let (entity_a, entity_b, n) = (contact.entity_a, contact.entity_b, contact.normal);
+        let (mut vel_a, pre_solve_vel_a, mass_a, restitution_a, material_a) =
+            dynamics.get_mut(entity_a).unwrap_or_else(|_| panic!("Could not unwrap dynamic entity {:?}", entity_a));
+        let (restitution_b, material_b) = statics.get(entity_b).unwrap_or_else(|_| panic!("Could not unwrap static entity {:?}", entity_b));
+        let pre_solve_normal_vel = Vector::dot(pre_solve_vel_a.0, n);
+        let normal_vel = Vector::dot(vel_a.0, n);
+        let material = PhysicsMaterial::of(restitution_a, material_a).combine(&PhysicsMaterial::of(restitution_b, material_b));
+        // Too slow to bounce, which keeps resting bodies from jittering
+        let restitution = if pre_solve_normal_vel.abs() < threshold { 0. } else { material.restitution };
+        let normal_delta = -normal_vel - restitution * pre_solve_normal_vel;
+        vel_a.0 += n * normal_delta;
+        let stopped_speed = pre_solve_normal_vel.max(contact.impulse.normal / mass_a.0);
+        let friction = friction_delta(vel_a.0, pre_solve_vel_a.0, n, stopped_speed, material.friction);
+        vel_a.0 += friction;
+        contact.impulse.normal -= normal_delta * mass_a.0;
+        contact.impulse.tangent += friction * mass_a.0;
+    }
+}
+
+// Coulomb friction: slows the tangential velocity by `friction` times the
+// normal speed the contact stopped. The result is bounded by the velocity from
+// before the solve, so a contact recorded on several substeps only counts once.
+// Bodies resting on each other fall together before the solve, so the stopped
+// speed is also read from the position pass's normal impulse.
+fn friction_delta(relative_vel: Vector, pre_solve_relative_vel: Vector, n: Vector, stopped_speed: Scalar, friction: Scalar) -> Vector {
+    let tangent_vel = relative_vel - n * relative_vel.dot(n);
+    let tangent_speed = tangent_vel.length();
+    if friction <= 0. || tangent_speed == 0. {
+        return Vector::ZERO;
+    }
+    let pre_solve_tangent_speed = (pre_solve_relative_vel - n * pre_solve_relative_vel.dot(n)).length();
+    let max_speed = (pre_solve_tangent_speed - friction * stopped_speed.max(0.)).max(0.);
+    tangent_vel * (tangent_speed.min(max_speed) / tangent_speed - 1.)
+}
+
+fn update_checksum(
+    query: Query<(&PhysicsId, &Pos, Option<&Vel>)>,
+    mut checksum: ResMut<PhysicsChecksum>,
+    config: Res<SolverConfig>,
+) {
+    if !config.deterministic {
+        return;
+    }
+
+    let mut bodies: Vec<_> = query.iter().collect();
+    bodies.sort_by_key(|(id, ..)| **id);
+
+    // FNV-1a over the raw bits, so any difference at all changes the checksum
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (id, pos, vel) in bodies {
+        let vel = vel.map_or(Vector::ZERO, |vel| vel.0);
+        let components = pos.0.to_array().into_iter().chain(vel.to_array()).flat_map(Scalar::to_le_bytes);
+        for byte in id.0.to_le_bytes().into_iter().chain(components) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    checksum.0 = hash;
+}
+
+// Bodies spawned with a `Transform` but no `Pos` start where the transform places them
+fn pos_from_transform_if_missing<C: Component>(
+    add: On<Add, C>,
+    query: Query<(), (With<Transform>, Without<Pos>)>,
+    mut commands: Commands,
 ) {
-    for (entity_a, entity_b, n) in contacts.0.iter().cloned() {
-        let (mut vel_a, pre_solve_vel_a, restitution_a) =
-            dynamics.get_mut(entity_a).expect(&format!("Could not unwrap dynamic entity {:?}", entity_a));
-        let restitution_b = statics.get(entity_b).expect(&format!("Could not unwrap static entity {:?}", entity_b));
-        let pre_solve_normal_vel = Vec2::dot(pre_solve_vel_a.0, n);
-        let normal_vel = Vec2::dot(vel_a.0, n);
-        let restitution = (restitution_a.0 + restitution_b.0) / 2.;
-        vel_a.0 += n * (-normal_vel - restitution * pre_solve_normal_vel);
+    if query.contains(add.entity) {
+        commands.entity(add.entity).insert((Pos::default(), PosFromTransform));
     }
 }
 
+// Bodies spawned with `PosFromTransform` start where their transform places them
+fn init_pos_from_transform(
+    add: On<Add, PosFromTransform>,
+    mut query: Query<(&mut Pos, Option<&mut PrevPos>, &Transform, Option<&ChildOf>)>,
+    parents: Query<&GlobalTransform>,
+    mut commands: Commands,
+) {
+    commands.entity(add.entity).remove::<PosFromTransform>();
+    let Ok((mut pos, prev_pos, transform, child_of)) = query.get_mut(add.entity) else {
+        return;
+    };
+
+    let parent = child_of.and_then(|child_of| parents.get(child_of.parent()).ok());
+    teleport(&mut pos, prev_pos, local_to_world(transform.translation, parent));
+}
+
+type TransformEditQuery<'a> = (
+    Ref<'a, Transform>,
+    &'a SyncedTranslation,
+    &'a mut Pos,
+    Option<&'a mut PrevPos>,
+    Option<&'a ChildOf>,
+);
+
+// Moves `Pos` to wherever the user put the `Transform`, keeping the velocity implied by `PrevPos`
+fn apply_transform_edits(
+    mut query: Query<TransformEditQuery>,
+    parents: Query<&GlobalTransform>,
+) {
+    for (transform, synced, mut pos, prev_pos, child_of) in query.iter_mut() {
+        // Transforms we never synced still hold whatever the spawner put there
+        let Some(synced) = synced.0 else {
+            continue;
+        };
+        if !transform.is_changed() || transform.translation == synced {
+            continue;
+        }
+
+        let parent = child_of.and_then(|child_of| parents.get(child_of.parent()).ok());
+        teleport(&mut pos, prev_pos, local_to_world(transform.translation, parent));
+    }
+}
+
+fn teleport(pos: &mut Pos, prev_pos: Option<Mut<PrevPos>>, target: Vector) {
+    let offset = target - pos.0;
+    pos.0 = target;
+    if let Some(mut prev_pos) = prev_pos {
+        prev_pos.0 += offset;
+    }
+}
+
+fn local_to_world(translation: Vec3, parent: Option<&GlobalTransform>) -> Vector {
+    match parent {
+        Some(parent) => from_translation(parent.transform_point(translation)),
+        None => from_translation(translation),
+    }
+}
+
+// Keeps the current depth, and goes through the parent's `GlobalTransform` for child entities
+fn world_to_local(world: Vector, transform: &Transform, parent: Option<&GlobalTransform>) -> Vec3 {
+    let translation = match parent {
+        Some(parent) => parent.affine().inverse().transform_point3(to_translation(world, 0.)),
+        None => to_translation(world, 0.),
+    };
+    to_translation(from_translation(translation), transform.translation.z)
+}
+
 // This applies the position component to the Bevy Transform component for rendering
 fn sync_transforms(
-    mut query: Query<(&mut Transform, &Pos)>,
+    mut query: Query<(&mut Transform, &mut SyncedTranslation, &Pos, Option<&ChildOf>), Without<TransformInterpolation>>,
+    parents: Query<&GlobalTransform>,
 ) {
-    for (mut transform, pos) in query.iter_mut() {
-        transform.translation = pos.0.extend(0.);
+    for (mut transform, mut synced, pos, child_of) in query.iter_mut() {
+        let parent = child_of.and_then(|child_of| parents.get(child_of.parent()).ok());
+        transform.translation = world_to_local(pos.0, &transform, parent);
+        synced.0 = Some(transform.translation);
+    }
+}
+
+type InterpolatedQuery<'a> = (
+    &'a mut Transform,
+    &'a mut SyncedTranslation,
+    &'a Pos,
+    &'a PrevPos,
+    &'a Vel,
+    &'a TransformInterpolation,
+    Option<&'a ChildOf>,
+);
+
+// Runs every frame, placing interpolated bodies according to how far we are into the next fixed step
+fn interpolate_transforms(
+    mut query: Query<InterpolatedQuery>,
+    parents: Query<&GlobalTransform>,
+    time: Res<Time<Fixed>>,
+    physics_time: Res<PhysicsTime>,
+) {
+    let overstep = time.overstep_fraction();
+    for (mut transform, mut synced, pos, prev_pos, vel, interpolation, child_of) in query.iter_mut() {
+        let rendered = match interpolation {
+            // Nothing moves while paused, so show where the bodies actually are
+            _ if !physics_time.is_running() => pos.0,
+            TransformInterpolation::Interpolate => prev_pos.0.lerp(pos.0, overstep as Scalar),
+            TransformInterpolation::Extrapolate => pos.0 + vel.0 * overstep as Scalar * DELTA_TIME,
+        };
+        let parent = child_of.and_then(|child_of| parents.get(child_of.parent()).ok());
+        transform.translation = world_to_local(rendered, &transform, parent);
+        synced.0 = Some(transform.translation);
     }
 }
 
@@ -264,4 +758,445 @@ fn clear_contacts(
 ) {
     contacts.0.clear();
     static_contacts.0.clear();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn run_script(steps: usize) -> PhysicsChecksum {
+        let mut app = test_app();
+        app.insert_resource(SolverConfig { deterministic: true, ..default() });
+        spawn_stack(app.world_mut());
+        app.world_mut().spawn(StaticCircleBundle {
+            pos: Pos(vector_xy(-120., 40.)),
+            collider: CircleCollider { radius: 30. },
+            ..default()
+        });
+        for i in 0..10 {
+            let pos = vector_xy(-120. + i as Scalar * 3., 150. + i as Scalar * 25.);
+            app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(pos, vector_xy(40., 0.), 1., 5.));
+        }
+        step(&mut app, steps);
+        *app.world().resource::<PhysicsChecksum>()
+    }
+
+    #[test]
+    fn user_systems_can_order_around_physics_sets() {
+        #[derive(Resource, Default)]
+        struct SeenContacts(usize);
+
+        let mut app = test_app();
+        app.init_resource::<SeenContacts>().add_systems(
+            FixedUpdate,
+            (|contacts: Res<StaticContacts>, mut seen: ResMut<SeenContacts>| {
+                seen.0 = contacts.0.len();
+            })
+            .before(PhysicsSet::Substep),
+        );
+        spawn_stack(app.world_mut());
+        step(&mut app, 10);
+
+        // Last step's contacts are still there until the substeps clear them
+        assert!(app.world().resource::<SeenContacts>().0 > 0);
+    }
+
+    #[test]
+    fn materials_pick_their_contact_restitution_and_friction() {
+        let mut app = test_app();
+        let concrete = PhysicsMaterial::new(0.8, 0.6);
+        let mud = PhysicsMaterial::new(0., 1.).with_combine(CombineRule::Min);
+        let rubber = PhysicsMaterial::new(0.9, 0.8);
+        for (x, material) in [(-1000., concrete), (1000., mud)] {
+            app.world_mut().spawn((
+                StaticBoxBundle { pos: Pos(vector_xy(x, -50.)), collider: BoxCollider { size: box_size(600., 100.) }, ..default() },
+                material,
+            ));
+        }
+        let mut ball = |pos: Vector, vel: Vector| app.world_mut().spawn((ParticleBundle::new_with_pos_vel_mass_radius(pos, vel, 1., 10.), rubber)).id();
+        let on_concrete = ball(vector_xy(-1000., 100.), Vector::ZERO);
+        let on_mud = ball(vector_xy(1000., 100.), Vector::ZERO);
+        let sliding = ball(vector_xy(800., 10.), vector_xy(200., 0.));
+
+        // Both land after about 40 steps, then the rubber bounces off the concrete only
+        step(&mut app, 45);
+        let mut highest = [Scalar::MIN; 2];
+        for _ in 0..30 {
+            step(&mut app, 1);
+            for (highest, ball) in highest.iter_mut().zip([on_concrete, on_mud]) {
+                *highest = highest.max(app.world().get::<Pos>(ball).unwrap().0.y);
+            }
+        }
+        assert!(highest[0] > 40., "rubber on concrete bounced to {}", highest[0]);
+        assert!(highest[1] < 11., "rubber on mud bounced to {}", highest[1]);
+
+        // Mud's friction stopped the sliding ball well before the end of the floor
+        let pos = app.world().get::<Pos>(sliding).unwrap().0;
+        let vel = app.world().get::<Vel>(sliding).unwrap().0;
+        assert!(vel.x.abs() < 1. && pos.x < 900., "sliding ball at {} moving at {}", pos, vel);
+    }
+
+    #[test]
+    fn friction_slows_a_ball_sliding_over_another_ball() {
+        let slide = |friction: Scalar| {
+            let mut app = test_app();
+            let material = PhysicsMaterial::new(0., friction);
+            app.world_mut().spawn(StaticBoxBundle { pos: Pos(vector_xy(0., -50.)), collider: BoxCollider { size: box_size(3000., 100.) }, ..default() });
+            // A ball so big and heavy that its top is nearly flat and it barely moves
+            app.world_mut().spawn((ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 500.), Vector::ZERO, 1e6, 500.), material));
+            let ball = app.world_mut().spawn((ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 1010.), vector_xy(100., 0.), 1., 10.), material)).id();
+            step(&mut app, 30);
+            app.world().get::<Vel>(ball).unwrap().0.x
+        };
+
+        let icy = slide(0.1);
+        let rough = slide(1.);
+        assert!(icy > 50., "icy ball slowed to {}", icy);
+        assert!(rough.abs() < 5., "rough ball still moving at {}", rough);
+    }
+
+    #[test]
+    fn dropped_ball_comes_fully_to_rest() {
+        let drop = |restitution_threshold: Scalar| {
+            let mut app = test_app();
+            app.insert_resource(SolverConfig { restitution_threshold, ..default() });
+            app.world_mut().spawn(StaticBoxBundle { pos: Pos(vector_xy(0., -50.)), collider: BoxCollider { size: box_size(300., 100.) }, ..default() });
+            let ball = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 100.), Vector::ZERO, 1., 10.)).id();
+            step(&mut app, 300);
+            (0..10).map(|_| {
+                step(&mut app, 1);
+                app.world().get::<Vel>(ball).unwrap().0.length()
+            }).fold(0., Scalar::max)
+        };
+
+        let fastest = drop(SolverConfig::default().restitution_threshold);
+        assert!(fastest < 1e-3, "still moving at {}", fastest);
+        let jittering = drop(0.);
+        assert!(jittering > 1., "without a threshold moving at {}", jittering);
+    }
+
+    #[test]
+    fn resting_stack_comes_fully_to_rest() {
+        let settle = |restitution_threshold: Scalar| {
+            let mut app = test_app();
+            app.insert_resource(SolverConfig { restitution_threshold, ..default() });
+            app.world_mut().spawn(StaticBoxBundle { pos: Pos(vector_xy(0., -50.)), collider: BoxCollider { size: box_size(300., 100.) }, ..default() });
+            // Three balls spawned resting on each other, so only dynamic contacts hold up the top two
+            let balls: Vec<Entity> = (0..3)
+                .map(|i| app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 10. + i as Scalar * 20.), Vector::ZERO, 1., 10.)).id())
+                .collect();
+            step(&mut app, 300);
+            (0..10).map(|_| {
+                step(&mut app, 1);
+                balls.iter().map(|ball| app.world().get::<Vel>(*ball).unwrap().0.length()).fold(0., Scalar::max)
+            }).fold(0., Scalar::max)
+        };
+
+        let fastest = settle(SolverConfig::default().restitution_threshold);
+        assert!(fastest < 1e-3, "still moving at {}", fastest);
+        let jittering = settle(0.);
+        assert!(jittering > 1., "without a threshold moving at {}", jittering);
+    }
+
+    #[test]
+    fn balls_colliding_head_on_swap_velocities() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let mut ball = |x: Scalar, vel: Scalar| {
+            app.world_mut().spawn(ParticleBundle {
+                restitution: Restitution(1.),
+                ..ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(x, 0.), vector_xy(vel, 0.), 1., 10.)
+            }).id()
+        };
+        let left = ball(-30., 100.);
+        let right = ball(30., -100.);
+
+        step(&mut app, 40);
+
+        let vel = |ball: Entity| app.world().get::<Vel>(ball).unwrap().0;
+        assert!(vel(left).distance(vector_xy(-100., 0.)) < 1., "left moving at {}", vel(left));
+        assert!(vel(right).distance(vector_xy(100., 0.)) < 1., "right moving at {}", vel(right));
+    }
+
+    #[test]
+    fn circle_overlapping_a_box_corner_is_pushed_out_to_touch_it() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        app.world_mut().spawn(StaticBoxBundle {
+            collider: BoxCollider { size: Vector::splat(100.) },
+            ..default()
+        });
+        let ball = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(55., 55.), Vector::ZERO, 1., 10.)).id();
+
+        step(&mut app, 1);
+
+        // Pushed diagonally away from the corner until it just touches it
+        let pos = app.world().get::<Pos>(ball).unwrap().0;
+        assert!((pos.distance(vector_xy(50., 50.)) - 10.).abs() < 0.1, "ball at {}", pos);
+        assert!((pos.x - pos.y).abs() < 1e-3, "ball at {}", pos);
+    }
+
+    #[test]
+    fn paused_physics_only_advances_requested_steps() {
+        let mut app = test_app();
+        let particles = spawn_stack(app.world_mut());
+        step(&mut app, 5);
+
+        app.world_mut().resource_mut::<PhysicsTime>().pause();
+        let paused = positions(&app, &particles);
+        step(&mut app, 5);
+        assert_eq!(paused, positions(&app, &particles));
+
+        app.world_mut().resource_mut::<PhysicsTime>().step(2);
+        step(&mut app, 1);
+        let first = positions(&app, &particles);
+        step(&mut app, 1);
+        let second = positions(&app, &particles);
+        step(&mut app, 3);
+        assert_ne!(paused, first);
+        assert_ne!(first, second);
+        assert_eq!(second, positions(&app, &particles));
+    }
+
+    #[test]
+    fn time_scale_stretches_the_fixed_timestep() {
+        let mut app = test_app();
+        app.world_mut().resource_mut::<PhysicsTime>().time_scale = 0.5;
+        app.world_mut().run_schedule(PreUpdate);
+
+        let timestep = app.world().resource::<Time<Fixed>>().timestep().as_secs_f64();
+        assert!((timestep - 2. * to_f64(DELTA_TIME)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stepping_leaves_the_fixed_timestep_alone() {
+        let mut app = test_app();
+        app.world_mut().run_schedule(PreUpdate);
+        step(&mut app, 1);
+        app.world_mut().resource_mut::<Time<Fixed>>().set_timestep_seconds(1.);
+        app.world_mut().run_schedule(PreUpdate);
+
+        let timestep = app.world().resource::<Time<Fixed>>().timestep().as_secs_f64();
+        assert_eq!(timestep, 1.);
+    }
+
+    // Also checks `Transform`'s Z is left alone, which only holds in 2D
+    #[cfg(not(feature = "3d"))]
+    #[test]
+    fn pos_follows_transform_on_spawn_and_edit() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let body = app.world_mut().spawn((
+            ParticleBundle::new_with_pos_and_vel(Vector::ZERO, vector_xy(64., 0.)),
+            Transform::from_xyz(30., 40., 5.),
+            PosFromTransform,
+        )).id();
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, vector_xy(30., 40.));
+        assert!(app.world().get::<PosFromTransform>(body).is_none());
+
+        step(&mut app, 1);
+        let transform = app.world().get::<Transform>(body).unwrap().translation;
+        assert_eq!(transform, Vec3::new(31., 40., 5.));
+
+        app.world_mut().get_mut::<Transform>(body).unwrap().translation = Vec3::new(-100., 0., 5.);
+        step(&mut app, 1);
+
+        // Teleported, but still moving at the same speed
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, vector_xy(-99., 0.));
+    }
+
+    #[test]
+    fn body_spawned_without_pos_starts_at_its_transform() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let body = app.world_mut().spawn((
+            Transform::from_xyz(30., 40., 0.),
+            PrevPos::default(),
+            Mass(1.),
+            CircleCollider { radius: 10. },
+            Vel::default(),
+            PreSolveVel::default(),
+            Restitution::default(),
+        )).id();
+        let wall = app.world_mut().spawn((Transform::from_xyz(-30., 0., 0.), BoxCollider::default())).id();
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, vector_xy(30., 40.));
+        assert_eq!(app.world().get::<Pos>(wall).unwrap().0, vector_xy(-30., 0.));
+
+        step(&mut app, 1);
+
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, vector_xy(30., 40.));
+    }
+
+    #[test]
+    fn body_spawned_at_the_origin_stays_there() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let body = app.world_mut().spawn((
+            ParticleBundle::new_with_pos_and_vel(Vector::ZERO, Vector::ZERO),
+            Transform::from_xyz(30., 40., 0.),
+        )).id();
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, Vector::ZERO);
+
+        step(&mut app, 1);
+
+        // The stale spawn transform is overwritten rather than taken as an edit
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, Vector::ZERO);
+        assert_eq!(app.world().get::<Transform>(body).unwrap().translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn parented_bodies_sync_through_global_transform() {
+        let mut app = test_app();
+        app.insert_resource(Gravity(Vector::ZERO));
+        let parent = app.world_mut().spawn((
+            Transform::from_xyz(50., 0., 0.),
+            GlobalTransform::from_xyz(50., 0., 0.),
+        )).id();
+        let body = app.world_mut().spawn((
+            ParticleBundle::default(),
+            Transform::from_xyz(10., 0., 0.),
+            ChildOf(parent),
+            PosFromTransform,
+        )).id();
+        assert_eq!(app.world().get::<Pos>(body).unwrap().0, vector_xy(60., 0.));
+
+        app.world_mut().get_mut::<Pos>(body).unwrap().0 = vector_xy(80., 20.);
+        app.world_mut().get_mut::<PrevPos>(body).unwrap().0 = vector_xy(80., 20.);
+        step(&mut app, 1);
+
+        assert_eq!(app.world().get::<Transform>(body).unwrap().translation, Vec3::new(30., 20., 0.));
+    }
+
+    #[test]
+    fn interpolation_is_chosen_per_entity() {
+        let mut app = test_app();
+        let spawn = |app: &mut App, y: Scalar, interpolation: Option<TransformInterpolation>| {
+            let mut entity = app.world_mut().spawn((
+                ParticleBundle::new_with_pos_and_vel(vector_xy(0., y), vector_xy(64., 0.)),
+                Transform::default(),
+            ));
+            if let Some(interpolation) = interpolation {
+                entity.insert(interpolation);
+            }
+            entity.id()
+        };
+        let snapped = spawn(&mut app, 0., None);
+        let interpolated = spawn(&mut app, 100., Some(TransformInterpolation::Interpolate));
+        let extrapolated = spawn(&mut app, 200., Some(TransformInterpolation::Extrapolate));
+
+        step(&mut app, 1);
+        app.world_mut().run_schedule(PostUpdate);
+
+        // No time has passed since the fixed step, so interpolation still shows the previous position
+        let x = |entity: Entity| app.world().get::<Transform>(entity).unwrap().translation.x;
+        assert_eq!(x(snapped), 1.);
+        assert_eq!(x(interpolated), 0.);
+        assert_eq!(x(extrapolated), 1.);
+    }
+
+    #[test]
+    fn independent_runs_produce_identical_checksums() {
+        let first = run_script(1000);
+        let second = run_script(1000);
+
+        assert_ne!(first, PhysicsChecksum::default());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn spawn_order_does_not_change_the_checksum() {
+        let run = |reversed: bool| {
+            let mut app = test_app();
+            app.insert_resource(SolverConfig { deterministic: true, ..default() });
+            let floor = (PhysicsId(100), StaticBoxBundle {
+                pos: Pos(vector_xy(0., -60.)),
+                collider: BoxCollider { size: vector_xy(300., 100.) },
+                ..default()
+            });
+            let mut bodies: Vec<_> = (0..40)
+                .map(|i| {
+                    let pos = vector_xy((i % 5) as Scalar * 25. - 50., (i / 5) as Scalar * 21.);
+                    let vel = vector_xy((i % 7) as Scalar * 30. - 90., 0.);
+                    (PhysicsId(i), ParticleBundle::new_with_pos_vel_mass_radius(pos, vel, 10., 10.))
+                })
+                .collect();
+
+            if reversed {
+                // Churn the entity allocator too, so the bodies end up on different entities
+                let placeholders: Vec<Entity> = (0..13).map(|_| app.world_mut().spawn(ParticleBundle::default()).id()).collect();
+                for entity in placeholders {
+                    app.world_mut().despawn(entity);
+                }
+                bodies.reverse();
+                app.world_mut().spawn_batch(bodies);
+                app.world_mut().spawn(floor);
+            } else {
+                app.world_mut().spawn(floor);
+                app.world_mut().spawn_batch(bodies);
+            }
+            step(&mut app, 300);
+            *app.world().resource::<PhysicsChecksum>()
+        };
+
+        let in_order = run(false);
+        assert_ne!(in_order, PhysicsChecksum::default());
+        assert_eq!(in_order, run(true));
+    }
+
+    #[cfg(feature = "3d")]
+    #[test]
+    fn spheres_stack_on_a_cuboid_in_3d() {
+        let mut app = test_app();
+        app.world_mut().spawn(StaticCuboidBundle {
+            pos: Pos(Vector::new(0., -50., 0.)),
+            collider: CuboidCollider { size: Vector::new(200., 100., 200.) },
+            ..default()
+        });
+        // A column leaning slightly towards +Z, so it has to hold up in all three dimensions
+        let spheres: Vec<Entity> = (0..5)
+            .map(|i| {
+                let pos = Vector::new(0., 10. + i as Scalar * 20., i as Scalar * 0.5);
+                app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 1., 10.)).id()
+            })
+            .collect();
+        let corner = app.world_mut().spawn((
+            ParticleBundle::new_with_pos_vel_mass_radius(Vector::new(60., 100., 60.), Vector::ZERO, 1., 10.),
+            Transform::default(),
+        )).id();
+
+        step(&mut app, 300);
+
+        for pair in positions(&app, &spheres).windows(2) {
+            assert!(pair[0].distance(pair[1]) > 19.5, "overlapping {:?}", pair);
+        }
+        // A lone sphere near the edge lands on top of the cuboid, and its transform follows in all three axes
+        let landed = app.world().get::<Pos>(corner).unwrap().0;
+        assert!((landed - Vector::new(60., 10., 60.)).length() < 0.5, "landed at {}", landed);
+        assert_eq!(app.world().get::<Transform>(corner).unwrap().translation, to_render(landed));
+    }
+
+    // Single precision only resolves about a tenth of a unit out there, so this needs `f64`
+    #[cfg(feature = "f64")]
+    #[test]
+    fn stacks_far_from_the_origin_settle_like_at_the_origin() {
+        let settle = |offset: Vector| {
+            let mut app = test_app();
+            let particles = spawn_stack_at(app.world_mut(), offset);
+            step(&mut app, 200);
+            let velocities: Vec<Vector> = particles.iter().map(|e| app.world().get::<Vel>(*e).unwrap().0).collect();
+            let positions: Vec<Vector> = positions(&app, &particles).into_iter().map(|pos| pos - offset).collect();
+            (positions, velocities)
+        };
+
+        let (near, near_velocities) = settle(Vector::ZERO);
+        let (far, far_velocities) = settle(vector_xy(1e6, 1e6));
+
+        for (near, far) in near.iter().zip(far.iter()) {
+            assert!(near.distance(*far) < 1e-3, "settled at {} instead of {}", far, near);
+        }
+        for (near, far) in near_velocities.iter().zip(far_velocities.iter()) {
+            assert!(near.distance(*far) < 0.01, "moving at {} instead of {}", far, near);
+        }
+    }
+}
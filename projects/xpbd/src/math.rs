@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+#[cfg(all(not(feature = "3d"), feature = "f64"))]
+use bevy::math::DVec2;
+#[cfg(all(feature = "3d", feature = "f64"))]
+use bevy::math::DVec3;
+
+// Positions, velocities and sizes are `Vector`s, so the same solver runs in 2D
+// and in 3D. The dimension is 2D unless the `3d` feature is on, so turning on
+// `3d` anywhere in the dependency tree wins over the default `2d`. In 2D the
+// world is the XY plane and `Transform`'s Z is left to the user for layering;
+// in 3D `Transform` translation and `Pos` are the same thing.
+//
+// All physics math is done in `Scalar`, which the `f64` feature switches from
+// `f32` to `f64` for worlds too large for single precision, again winning over
+// the default `f32`. Bevy itself stays `f32`, so values are only converted when
+// talking to transforms and rendering.
+
+#[cfg(not(feature = "f64"))]
+pub type Scalar = f32;
+#[cfg(feature = "f64")]
+pub type Scalar = f64;
+
+#[cfg(not(feature = "f64"))]
+pub(crate) use std::f32::consts;
+#[cfg(feature = "f64")]
+pub(crate) use std::f64::consts;
+
+#[cfg(all(not(feature = "3d"), not(feature = "f64")))]
+pub type Vector = Vec2;
+#[cfg(all(not(feature = "3d"), feature = "f64"))]
+pub type Vector = DVec2;
+#[cfg(all(feature = "3d", not(feature = "f64")))]
+pub type Vector = Vec3;
+#[cfg(all(feature = "3d", feature = "f64"))]
+pub type Vector = DVec3;
+
+// Rotations and angular velocities: an angle in the XY plane in 2D, and an
+// axis scaled by the angle in 3D
+#[cfg(not(feature = "3d"))]
+pub type AngularVector = Scalar;
+#[cfg(feature = "3d")]
+pub type AngularVector = Vector;
+
+// What Bevy uses for a `Vector`, whatever the precision
+#[cfg(not(feature = "3d"))]
+pub type RenderVector = Vec2;
+#[cfg(feature = "3d")]
+pub type RenderVector = Vec3;
+
+#[cfg(not(feature = "3d"))]
+pub const DIMENSIONS: usize = 2;
+#[cfg(feature = "3d")]
+pub const DIMENSIONS: usize = 3;
+
+// Integer coordinates of a cell in a uniform grid
+#[cfg(not(feature = "3d"))]
+pub(crate) type Cell = IVec2;
+#[cfg(feature = "3d")]
+pub(crate) type Cell = IVec3;
+
+// A vector in the XY plane, which is the whole world in 2D
+#[cfg(not(feature = "3d"))]
+pub fn vector_xy(x: Scalar, y: Scalar) -> Vector {
+    Vector::new(x, y)
+}
+
+#[cfg(feature = "3d")]
+pub fn vector_xy(x: Scalar, y: Scalar) -> Vector {
+    Vector::new(x, y, 0.)
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn to_render(vector: Vector) -> RenderVector {
+    vector
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn from_render(vector: RenderVector) -> Vector {
+    vector
+}
+
+#[cfg(all(not(feature = "3d"), feature = "f64"))]
+pub fn to_render(vector: Vector) -> RenderVector {
+    vector.as_vec2()
+}
+
+#[cfg(all(not(feature = "3d"), feature = "f64"))]
+pub fn from_render(vector: RenderVector) -> Vector {
+    vector.as_dvec2()
+}
+
+#[cfg(all(feature = "3d", feature = "f64"))]
+pub fn to_render(vector: Vector) -> RenderVector {
+    vector.as_vec3()
+}
+
+#[cfg(all(feature = "3d", feature = "f64"))]
+pub fn from_render(vector: RenderVector) -> Vector {
+    vector.as_dvec3()
+}
+
+// A `Scalar` as the `f32` Bevy renders with, or the `f64` diagnostics are kept in
+#[cfg(not(feature = "f64"))]
+pub fn to_f32(scalar: Scalar) -> f32 {
+    scalar
+}
+
+#[cfg(not(feature = "f64"))]
+pub fn to_f64(scalar: Scalar) -> f64 {
+    scalar.into()
+}
+
+#[cfg(feature = "f64")]
+pub fn to_f32(scalar: Scalar) -> f32 {
+    scalar as f32
+}
+
+#[cfg(feature = "f64")]
+pub fn to_f64(scalar: Scalar) -> f64 {
+    scalar
+}
+
+// Box size with these extents in the XY plane, and in 3D deep enough for
+// everything near z = 0 to land on it
+pub fn box_size(x: Scalar, y: Scalar) -> Vector {
+    let mut size = Vector::splat(1000.);
+    size.x = x;
+    size.y = y;
+    size
+}
+
+// `Transform` translation for a world position, keeping `z` in 2D
+#[cfg(not(feature = "3d"))]
+pub fn to_translation(pos: Vector, z: f32) -> Vec3 {
+    to_render(pos).extend(z)
+}
+
+#[cfg(feature = "3d")]
+pub fn to_translation(pos: Vector, _z: f32) -> Vec3 {
+    to_render(pos)
+}
+
+#[cfg(not(feature = "3d"))]
+pub(crate) fn from_translation(translation: Vec3) -> Vector {
+    from_render(translation.truncate())
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn from_translation(translation: Vec3) -> Vector {
+    from_render(translation)
+}
+
+// How far a point `r` away from a body's center moves when the body turns by `rotation`
+#[cfg(not(feature = "3d"))]
+pub(crate) fn rotation_displacement(rotation: AngularVector, r: Vector) -> Vector {
+    r.perp() * rotation
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn rotation_displacement(rotation: AngularVector, r: Vector) -> Vector {
+    rotation.cross(r)
+}
+
+// Turning effect of pushing a body by `p` at a point `r` away from its center
+#[cfg(not(feature = "3d"))]
+pub(crate) fn torque(r: Vector, p: Vector) -> AngularVector {
+    r.perp_dot(p)
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn torque(r: Vector, p: Vector) -> AngularVector {
+    r.cross(p)
+}
+
+// `rotation` cut down to an angle of at most `max`
+#[cfg(not(feature = "3d"))]
+pub(crate) fn clamp_rotation(rotation: AngularVector, max: Scalar) -> AngularVector {
+    rotation.clamp(-max, max)
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn clamp_rotation(rotation: AngularVector, max: Scalar) -> AngularVector {
+    rotation.clamp_length_max(max)
+}
+
+// Cell containing a point, for cells of unit size
+#[cfg(not(feature = "3d"))]
+pub(crate) fn to_cell(pos: Vector) -> Cell {
+    pos.floor().as_ivec2()
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn to_cell(pos: Vector) -> Cell {
+    pos.floor().as_ivec3()
+}
+
+#[cfg(not(feature = "3d"))]
+pub(crate) fn from_cell(cell: Cell) -> Vector {
+    from_render(cell.as_vec2())
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn from_cell(cell: Cell) -> Vector {
+    from_render(cell.as_vec3())
+}
+
+// Offsets of all cells at most `reach` cells away along every axis
+#[cfg(not(feature = "3d"))]
+pub(crate) fn cells_within(reach: i32) -> impl Iterator<Item = Cell> {
+    (-reach..=reach).flat_map(move |x| (-reach..=reach).map(move |y| IVec2::new(x, y)))
+}
+
+#[cfg(feature = "3d")]
+pub(crate) fn cells_within(reach: i32) -> impl Iterator<Item = Cell> {
+    (-reach..=reach).flat_map(move |x| (-reach..=reach).flat_map(move |y| (-reach..=reach).map(move |z| IVec3::new(x, y, z))))
+}
+
+// The cell and all cells around it
+pub(crate) fn neighbourhood(center: Cell) -> impl Iterator<Item = Cell> {
+    cells_within(1).map(move |offset| center + offset)
+}
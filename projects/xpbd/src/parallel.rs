@@ -0,0 +1,98 @@
+use bevy::ecs::entity::EntityHashSet;
+use bevy::prelude::*;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use crate::CollisionPair;
+
+// Greedy graph colouring over bodies: every pair goes into the first batch where
+// neither of its bodies already appears, so the pairs of one batch never touch
+// the same body and can be solved concurrently.
+pub(crate) fn colour_pairs(pairs: &[CollisionPair]) -> Vec<Vec<CollisionPair>> {
+    let mut batches: Vec<(Vec<CollisionPair>, EntityHashSet)> = Vec::new();
+
+    for &CollisionPair { entity_a, entity_b } in pairs {
+        let free = batches
+            .iter()
+            .position(|(_, bodies)| !bodies.contains(&entity_a) && !bodies.contains(&entity_b));
+        let index = free.unwrap_or_else(|| {
+            batches.push(Default::default());
+            batches.len() - 1
+        });
+
+        let (batch, bodies) = &mut batches[index];
+        batch.push(CollisionPair { entity_a, entity_b });
+        bodies.insert(entity_a);
+        bodies.insert(entity_b);
+    }
+
+    batches.into_iter().map(|(batch, _)| batch).collect()
+}
+
+// Chunk length that spreads `len` items over the compute threads, or keeps them
+// in a single chunk when running serially.
+pub(crate) fn chunk_size(len: usize, parallel: bool) -> usize {
+    if !parallel {
+        return len.max(1);
+    }
+    let threads = ComputeTaskPool::get_or_init(TaskPool::default).thread_num();
+    len.div_ceil(threads).max(1)
+}
+
+// Runs `f` on every chunk using the compute task pool. Results come back in
+// chunk order, so the output does not depend on thread scheduling.
+pub(crate) fn par_map_chunks<C, R>(chunks: impl Iterator<Item = C>, f: impl Fn(C) -> R + Sync) -> Vec<R>
+where
+    C: Send,
+    R: Send + 'static,
+{
+    let f = &f;
+    ComputeTaskPool::get_or_init(TaskPool::default).scope(|scope| {
+        for chunk in chunks {
+            scope.spawn(async move { f(chunk) });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{SolverConfig, Vector};
+    use crate::test_utils::*;
+
+    fn stack_positions(parallel: bool, steps: usize) -> Vec<Vector> {
+        let mut app = test_app();
+        app.insert_resource(SolverConfig { parallel, ..default() });
+        let particles = spawn_stack(app.world_mut());
+        step(&mut app, steps);
+        positions(&app, &particles)
+    }
+
+    #[test]
+    fn parallel_solver_matches_serial() {
+        let serial = stack_positions(false, 60);
+        let parallel = stack_positions(true, 60);
+
+        for (a, b) in serial.iter().zip(parallel.iter()) {
+            assert!(a.distance(*b) < 0.5, "serial {:?} vs parallel {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn batches_never_share_a_body() {
+        let e: Vec<Entity> = (0..5).map(Entity::from_raw_u32).map(Option::unwrap).collect();
+        let pairs: Vec<CollisionPair> = [(0, 1), (1, 2), (2, 3), (3, 4), (1, 0), (0, 4)]
+            .into_iter()
+            .map(|(a, b)| CollisionPair { entity_a: e[a], entity_b: e[b] })
+            .collect();
+
+        let batches = colour_pairs(&pairs);
+
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), pairs.len());
+        for batch in batches.iter() {
+            let mut bodies = EntityHashSet::default();
+            for pair in batch.iter() {
+                assert!(bodies.insert(pair.entity_a) && bodies.insert(pair.entity_b));
+            }
+        }
+    }
+}
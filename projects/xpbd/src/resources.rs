@@ -1,27 +1,129 @@
 use bevy::prelude::*;
+use serde::Deserialize;
+use crate::*;
 
-#[derive(Resource, Debug)]
-pub struct Gravity(pub Vec2);
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Gravity(pub Vector);
 
 impl Default for Gravity {
     fn default() -> Self {
-        Self(Vec2::new(0., -9.81))
+        Self(vector_xy(0., -9.81))
     }
 }
 
+// Two dynamic bodies close enough to touch during the step. Every pair is
+// listed in both orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CollisionPair {
+    pub entity_a: Entity,
+    pub entity_b: Entity,
+}
+
 #[derive(Resource, Debug, Default)]
-pub struct CollisionPairs(pub Vec<(Entity, Entity)>);
+pub struct CollisionPairs(pub Vec<CollisionPair>);
+
+// Contacts between dynamic bodies
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct Contacts(pub ContactList);
+
+// Contacts of dynamic bodies with statics, the static being `entity_b`
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct StaticContacts(pub ContactList);
 
-#[derive(Resource, Debug)]
-pub struct Contacts(pub Vec<(Entity, Entity, Vec2)>);
+// Collision pairs partitioned so that no body appears twice in the same batch
+#[derive(Resource, Debug, Default)]
+pub struct CollisionBatches(pub Vec<Vec<CollisionPair>>);
+
+#[derive(Resource, Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SolverConfig {
+    // Solve independent constraints on the compute task pool
+    pub parallel: bool,
+    // Solve bodies in `PhysicsId` order and update `PhysicsChecksum` every step
+    pub deterministic: bool,
+    // Not a speed but a multiple of |gravity| * `SUB_DT`, the speed gravity
+    // adds in one substep: contacts approaching slower than that many times
+    // it are inelastic, so resting bodies stop bouncing. The XPBD paper uses
+    // 2, but velocities here are only updated once per step, during which a
+    // resting body picks up `NUM_SUBSTEPS` times that much speed.
+    // `restitution_threshold_speed` turns it into the actual speed.
+    pub restitution_threshold: Scalar,
+}
 
-impl Default for Contacts {
+impl Default for SolverConfig {
     fn default() -> Self {
-        Self(Vec::new())
+        Self {
+            parallel: true,
+            deterministic: false,
+            restitution_threshold: 2. * NUM_SUBSTEPS as Scalar,
+        }
     }
 }
 
-#[derive(Resource, Debug, Default)]
-pub struct StaticContacts(pub Vec<(Entity, Entity, Vec2)>);
+impl SolverConfig {
+    // Approach speed below which contacts do not bounce
+    pub fn restitution_threshold_speed(&self, gravity: &Gravity) -> Scalar {
+        self.restitution_threshold * gravity.0.length() * SUB_DT
+    }
+}
+
+// Next id handed out to bodies, joints and soft bodies spawned without a `PhysicsId`
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct NextPhysicsId(pub u64);
 
+// Hash of every body's position and velocity, updated each step in deterministic mode
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicsChecksum(pub u64);
 
+// Pause, single-step and slow motion for the simulation. The time scale works
+// by stretching the `Time<Fixed>` timestep, so each step still advances the
+// simulation by `DELTA_TIME` and results stay the same, just slower.
+#[derive(Resource, Debug, Clone)]
+pub struct PhysicsTime {
+    pub paused: bool,
+    // Steps to run while paused, consumed one per fixed step
+    pub pending_steps: u32,
+    pub time_scale: f32,
+    pub(crate) running: bool,
+}
+
+impl Default for PhysicsTime {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            pending_steps: 0,
+            time_scale: 1.,
+            running: true,
+        }
+    }
+}
+
+impl PhysicsTime {
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    // Runs `steps` more fixed steps and pauses again
+    pub fn step(&mut self, steps: u32) {
+        self.paused = true;
+        self.pending_steps += steps;
+    }
+
+    // Whether the simulation advances during the current fixed step
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+}
@@ -0,0 +1,299 @@
+use bevy::prelude::*;
+use crate::*;
+
+// Ropes and cloth are particles linked by `DistanceJoint`s. Stretch joints link
+// neighbours; softer bending joints skip one particle and resist folding.
+// Pinned particles are spawned as statics without a collider, so they stay put
+// and the rest of the rope hangs off them. With a break force set, every joint
+// gets a `BreakForce` and the rope or cloth tears where it is pulled too hard.
+
+// Particles and joints spawned by `RopeBuilder` or `ClothBuilder`
+#[derive(Debug, Clone, Default)]
+pub struct SpawnedChain {
+    pub particles: Vec<Entity>,
+    pub stretch_joints: Vec<Entity>,
+    pub bend_joints: Vec<Entity>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ChainSettings {
+    particle_mass: Scalar,
+    particle_radius: Scalar,
+    compliance: Scalar,
+    bend_compliance: Scalar,
+    break_force: Option<Scalar>,
+}
+
+impl ChainSettings {
+    fn new(spacing: Scalar) -> Self {
+        Self {
+            particle_mass: 1.,
+            particle_radius: spacing / 2.,
+            compliance: 0.,
+            bend_compliance: 0.001,
+            break_force: None,
+        }
+    }
+
+    fn spawn_particle(&self, commands: &mut Commands, pos: Vector, pinned: bool) -> Entity {
+        if pinned {
+            commands.spawn((Name::new("Pin"), Pos(pos))).id()
+        } else {
+            commands.spawn((
+                Name::new("Particle"),
+                ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, self.particle_mass, self.particle_radius),
+            )).id()
+        }
+    }
+
+    fn spawn_joint(&self, commands: &mut Commands, body_a: Entity, body_b: Entity, length: Scalar, compliance: Scalar) -> Entity {
+        let mut joint = commands.spawn(DistanceJoint::new(body_a, body_b, length).with_compliance(compliance));
+        if let Some(break_force) = self.break_force {
+            joint.insert(BreakForce(break_force));
+        }
+        joint.id()
+    }
+}
+
+// A straight rope of `segments + 1` particles from `start` to `end`
+#[derive(Debug, Clone)]
+pub struct RopeBuilder {
+    start: Vector,
+    end: Vector,
+    segments: usize,
+    pin_start: bool,
+    pin_end: bool,
+    settings: ChainSettings,
+}
+
+impl RopeBuilder {
+    pub fn new(start: Vector, end: Vector, segments: usize) -> Self {
+        let segments = segments.max(1);
+        Self {
+            start,
+            end,
+            segments,
+            pin_start: false,
+            pin_end: false,
+            settings: ChainSettings::new(start.distance(end) / segments as Scalar),
+        }
+    }
+
+    pub fn pin_start(self) -> Self {
+        Self { pin_start: true, ..self }
+    }
+
+    pub fn pin_end(self) -> Self {
+        Self { pin_end: true, ..self }
+    }
+
+    pub fn with_particle_mass(mut self, particle_mass: Scalar) -> Self {
+        self.settings.particle_mass = particle_mass;
+        self
+    }
+
+    pub fn with_particle_radius(mut self, particle_radius: Scalar) -> Self {
+        self.settings.particle_radius = particle_radius;
+        self
+    }
+
+    // Compliance of the stretch joints
+    pub fn with_compliance(mut self, compliance: Scalar) -> Self {
+        self.settings.compliance = compliance;
+        self
+    }
+
+    // Compliance of the bending joints, 0 makes the rope a rigid rod
+    pub fn with_bend_compliance(mut self, bend_compliance: Scalar) -> Self {
+        self.settings.bend_compliance = bend_compliance;
+        self
+    }
+
+    pub fn with_break_force(mut self, break_force: Scalar) -> Self {
+        self.settings.break_force = Some(break_force);
+        self
+    }
+
+    pub fn spawn(&self, commands: &mut Commands) -> SpawnedChain {
+        let positions: Vec<Vector> = (0..=self.segments)
+            .map(|i| self.start.lerp(self.end, i as Scalar / self.segments as Scalar))
+            .collect();
+        let last = self.segments;
+
+        let particles: Vec<Entity> = positions
+            .iter()
+            .enumerate()
+            .map(|(i, pos)| {
+                let pinned = (i == 0 && self.pin_start) || (i == last && self.pin_end);
+                self.settings.spawn_particle(commands, *pos, pinned)
+            })
+            .collect();
+
+        let mut chain = SpawnedChain { particles, ..default() };
+        for i in 0..last {
+            chain.stretch_joints.push(link(commands, &self.settings, &chain.particles, &positions, i, i + 1, self.settings.compliance));
+        }
+        for i in 0..last.saturating_sub(1) {
+            chain.bend_joints.push(link(commands, &self.settings, &chain.particles, &positions, i, i + 2, self.settings.bend_compliance));
+        }
+        chain
+    }
+}
+
+// A sheet of `columns` by `rows` particles with its top left particle at
+// `origin`, stored row by row from the top
+#[derive(Debug, Clone)]
+pub struct ClothBuilder {
+    origin: Vector,
+    columns: usize,
+    rows: usize,
+    spacing: Scalar,
+    pinned: Vec<(usize, usize)>,
+    settings: ChainSettings,
+}
+
+impl ClothBuilder {
+    pub fn new(origin: Vector, columns: usize, rows: usize, spacing: Scalar) -> Self {
+        Self {
+            origin,
+            columns,
+            rows,
+            spacing,
+            pinned: Vec::new(),
+            settings: ChainSettings::new(spacing),
+        }
+    }
+
+    // Pins the particle in `column` of `row`, counted from the top left
+    pub fn pin(mut self, column: usize, row: usize) -> Self {
+        self.pinned.push((column, row));
+        self
+    }
+
+    // Pins every `step`th particle of the top row, like a curtain
+    pub fn pin_top_row(mut self, step: usize) -> Self {
+        for column in (0..self.columns).step_by(step.max(1)) {
+            self.pinned.push((column, 0));
+        }
+        self
+    }
+
+    pub fn with_particle_mass(mut self, particle_mass: Scalar) -> Self {
+        self.settings.particle_mass = particle_mass;
+        self
+    }
+
+    pub fn with_particle_radius(mut self, particle_radius: Scalar) -> Self {
+        self.settings.particle_radius = particle_radius;
+        self
+    }
+
+    // Compliance of the stretch joints
+    pub fn with_compliance(mut self, compliance: Scalar) -> Self {
+        self.settings.compliance = compliance;
+        self
+    }
+
+    // Compliance of the bending joints, 0 keeps the cloth from folding at all
+    pub fn with_bend_compliance(mut self, bend_compliance: Scalar) -> Self {
+        self.settings.bend_compliance = bend_compliance;
+        self
+    }
+
+    pub fn with_break_force(mut self, break_force: Scalar) -> Self {
+        self.settings.break_force = Some(break_force);
+        self
+    }
+
+    pub fn index(&self, column: usize, row: usize) -> usize {
+        row * self.columns + column
+    }
+
+    pub fn spawn(&self, commands: &mut Commands) -> SpawnedChain {
+        let positions: Vec<Vector> = (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .map(|(column, row)| self.origin + vector_xy(column as Scalar, -(row as Scalar)) * self.spacing)
+            .collect();
+
+        let particles: Vec<Entity> = positions
+            .iter()
+            .enumerate()
+            .map(|(i, pos)| {
+                let pinned = self.pinned.contains(&(i % self.columns, i / self.columns));
+                self.settings.spawn_particle(commands, *pos, pinned)
+            })
+            .collect();
+
+        let mut chain = SpawnedChain { particles, ..default() };
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let i = self.index(column, row);
+                let (stretch, bend) = (self.settings.compliance, self.settings.bend_compliance);
+                if column + 1 < self.columns {
+                    chain.stretch_joints.push(link(commands, &self.settings, &chain.particles, &positions, i, i + 1, stretch));
+                }
+                if row + 1 < self.rows {
+                    chain.stretch_joints.push(link(commands, &self.settings, &chain.particles, &positions, i, i + self.columns, stretch));
+                }
+                if column + 2 < self.columns {
+                    chain.bend_joints.push(link(commands, &self.settings, &chain.particles, &positions, i, i + 2, bend));
+                }
+                if row + 2 < self.rows {
+                    chain.bend_joints.push(link(commands, &self.settings, &chain.particles, &positions, i, i + 2 * self.columns, bend));
+                }
+            }
+        }
+        chain
+    }
+}
+
+// Joins particles `a` and `b` at the distance they were spawned at
+fn link(
+    commands: &mut Commands,
+    settings: &ChainSettings,
+    particles: &[Entity],
+    positions: &[Vector],
+    a: usize,
+    b: usize,
+    compliance: Scalar,
+) -> Entity {
+    let length = positions[a].distance(positions[b]);
+    settings.spawn_joint(commands, particles[a], particles[b], length, compliance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn hang_rope(break_force: Scalar) -> (App, SpawnedChain) {
+        let mut app = test_app();
+        // Nine particles of mass 1 hang off the pin, so the top joint holds about 9 * 500
+        let rope = RopeBuilder::new(Vector::ZERO, vector_xy(0., -90.), 9)
+            .pin_start()
+            .with_break_force(break_force)
+            .spawn(&mut app.world_mut().commands());
+        app.world_mut().flush();
+        step(&mut app, 30);
+        (app, rope)
+    }
+
+    #[test]
+    fn pinned_rope_hangs_at_its_length() {
+        let (app, rope) = hang_rope(10000.);
+        assert!(rope.stretch_joints.iter().all(|joint| app.world().get_entity(*joint).is_ok()));
+
+        let positions = positions(&app, &rope.particles);
+        assert_eq!(positions[0], Vector::ZERO);
+        assert!((positions[9].y + 90.).abs() < 1., "end {:?}", positions[9]);
+    }
+
+    #[test]
+    fn rope_tears_where_the_force_exceeds_the_break_force() {
+        let (app, rope) = hang_rope(3000.);
+        let top_joint = rope.stretch_joints[0];
+        let bottom_joint = rope.stretch_joints[8];
+        assert!(app.world().get_entity(top_joint).is_err());
+        assert!(app.world().get_entity(bottom_joint).is_ok());
+    }
+}
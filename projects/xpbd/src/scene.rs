@@ -0,0 +1,287 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::platform::collections::HashSet;
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::*;
+
+// Loads `.physics.ron` / `.physics.json` scenes and (re)spawns them for every
+// `PhysicsSceneRoot`. Needs the asset plugin, so it is separate from `XPBDPlugin`.
+#[derive(Debug, Default)]
+pub struct PhysicsScenePlugin;
+
+impl Plugin for PhysicsScenePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<PhysicsScene>()
+            .init_asset_loader::<PhysicsSceneLoader>()
+            .add_systems(Update, spawn_physics_scenes);
+    }
+}
+
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct PhysicsScene {
+    #[serde(default)]
+    pub gravity: Option<Vector>,
+    #[serde(default)]
+    pub config: Option<SceneConfig>,
+    pub bodies: Vec<SceneBody>,
+}
+
+// Solver settings a scene overrides, the ones left out keep their current values
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SceneConfig {
+    pub parallel: Option<bool>,
+    pub deterministic: Option<bool>,
+    // A multiple of |gravity| * `SUB_DT` like `SolverConfig::restitution_threshold`, not a speed
+    pub restitution_threshold: Option<Scalar>,
+}
+
+impl SceneConfig {
+    pub fn apply(&self, config: &mut SolverConfig) {
+        if let Some(parallel) = self.parallel {
+            config.parallel = parallel;
+        }
+        if let Some(deterministic) = self.deterministic {
+            config.deterministic = deterministic;
+        }
+        if let Some(restitution_threshold) = self.restitution_threshold {
+            config.restitution_threshold = restitution_threshold;
+        }
+    }
+}
+
+// Anything left out falls back to the component defaults
+#[derive(Debug, Clone, Deserialize)]
+pub enum SceneBody {
+    Particle {
+        pos: Vector,
+        #[serde(default)]
+        vel: Vector,
+        mass: Option<Scalar>,
+        radius: Option<Scalar>,
+        restitution: Option<Scalar>,
+    },
+    StaticCircle {
+        pos: Vector,
+        radius: Option<Scalar>,
+        restitution: Option<Scalar>,
+    },
+    StaticBox {
+        pos: Vector,
+        size: Option<Vector>,
+        restitution: Option<Scalar>,
+    },
+}
+
+impl PhysicsScene {
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, BevyError> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, BevyError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    // Spawns every body and applies whatever gravity and config the scene
+    // specifies, returning the new entities
+    pub fn spawn(&self, commands: &mut Commands) -> Vec<Entity> {
+        if let Some(gravity) = self.gravity {
+            commands.insert_resource(Gravity(gravity));
+        }
+        if let Some(scene_config) = self.config.clone() {
+            commands.queue(move |world: &mut World| {
+                scene_config.apply(&mut world.get_resource_or_init::<SolverConfig>());
+            });
+        }
+
+        self.bodies.iter().map(|body| body.spawn(commands)).collect()
+    }
+}
+
+impl SceneBody {
+    fn spawn(&self, commands: &mut Commands) -> Entity {
+        match *self {
+            SceneBody::Particle { pos, vel, mass, radius, restitution } => {
+                let defaults = ParticleBundle::default();
+                commands.spawn((
+                    Name::new("Particle"),
+                    ParticleBundle {
+                        mass: mass.map_or(defaults.mass, Mass),
+                        collider: radius.map_or(defaults.collider, |radius| CircleCollider { radius }),
+                        restitution: restitution.map_or(defaults.restitution, Restitution),
+                        ..ParticleBundle::new_with_pos_and_vel(pos, vel)
+                    },
+                    Transform::from_translation(to_translation(pos, 0.)),
+                )).id()
+            }
+            SceneBody::StaticCircle { pos, radius, restitution } => {
+                let defaults = StaticCircleBundle::default();
+                commands.spawn((
+                    Name::new("Static Circle"),
+                    StaticCircleBundle {
+                        pos: Pos(pos),
+                        collider: radius.map_or(defaults.collider, |radius| CircleCollider { radius }),
+                        restitution: restitution.map_or(defaults.restitution, Restitution),
+                    },
+                    Transform::from_translation(to_translation(pos, 0.)),
+                )).id()
+            }
+            SceneBody::StaticBox { pos, size, restitution } => {
+                let defaults = StaticBoxBundle::default();
+                commands.spawn((
+                    Name::new("Static Box"),
+                    StaticBoxBundle {
+                        pos: Pos(pos),
+                        collider: size.map_or(defaults.collider, |size| BoxCollider { size }),
+                        restitution: restitution.map_or(defaults.restitution, Restitution),
+                    },
+                    Transform::from_translation(to_translation(pos, 0.)),
+                )).id()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, TypePath)]
+pub struct PhysicsSceneLoader;
+
+impl AssetLoader for PhysicsSceneLoader {
+    type Asset = PhysicsScene;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<PhysicsScene, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let is_json = load_context.path().extension().is_some_and(|extension| extension == "json");
+        if is_json {
+            PhysicsScene::from_json(&bytes)
+        } else {
+            PhysicsScene::from_ron(&bytes)
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["physics.ron", "physics.json"]
+    }
+}
+
+// Spawns the scene referenced by this entity once it has loaded, and again whenever the file changes
+#[derive(Component, Debug)]
+pub struct PhysicsSceneRoot(pub Handle<PhysicsScene>);
+
+// Bodies currently spawned for a `PhysicsSceneRoot`, despawned on reload
+#[derive(Component, Debug, Default)]
+pub struct PhysicsSceneBodies(pub Vec<Entity>);
+
+fn spawn_physics_scenes(
+    mut commands: Commands,
+    mut events: MessageReader<AssetEvent<PhysicsScene>>,
+    scenes: Res<Assets<PhysicsScene>>,
+    roots: Query<(Entity, &PhysicsSceneRoot, Option<&PhysicsSceneBodies>)>,
+) {
+    let modified: HashSet<AssetId<PhysicsScene>> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (root, scene_root, bodies) in roots.iter() {
+        let id = scene_root.0.id();
+        if bodies.is_some() && !modified.contains(&id) {
+            continue;
+        }
+        let Some(scene) = scenes.get(id) else {
+            continue;
+        };
+
+        if let Some(bodies) = bodies {
+            info!("Reloading physics scene {:?}", id);
+            for entity in bodies.0.iter() {
+                commands.entity(*entity).try_despawn();
+            }
+        }
+        let spawned = scene.spawn(&mut commands);
+        commands.entity(root).insert(PhysicsSceneBodies(spawned));
+    }
+}
+
+// The scenes below are written with 2D vectors
+#[cfg(all(test, not(feature = "3d")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ron_and_json_describe_the_same_scene() {
+        let ron = br#"(
+            gravity: Some((0., -100.)),
+            config: Some((deterministic: Some(true))),
+            bodies: [
+                Particle(pos: (0., 50.), vel: (10., 0.), mass: Some(2.), radius: Some(5.)),
+                StaticCircle(pos: (-40., 0.)),
+                StaticBox(pos: (0., -60.), size: Some((300., 20.)), restitution: Some(0.)),
+            ],
+        )"#;
+        let json = br#"{
+            "gravity": [0.0, -100.0],
+            "config": { "deterministic": true },
+            "bodies": [
+                { "Particle": { "pos": [0.0, 50.0], "vel": [10.0, 0.0], "mass": 2.0, "radius": 5.0 } },
+                { "StaticCircle": { "pos": [-40.0, 0.0] } },
+                { "StaticBox": { "pos": [0.0, -60.0], "size": [300.0, 20.0], "restitution": 0.0 } }
+            ]
+        }"#;
+
+        for scene in [PhysicsScene::from_ron(ron).unwrap(), PhysicsScene::from_json(json).unwrap()] {
+            let mut world = World::new();
+            world.insert_resource(SolverConfig { parallel: false, ..default() });
+            let entities = scene.spawn(&mut world.commands());
+            world.flush();
+
+            assert_eq!(world.resource::<Gravity>().0, vector_xy(0., -100.));
+            // Only the settings the scene mentions change
+            assert!(world.resource::<SolverConfig>().deterministic);
+            assert!(!world.resource::<SolverConfig>().parallel);
+            assert_eq!(world.get::<Mass>(entities[0]).unwrap().0, 2.);
+            assert_eq!(world.get::<CircleCollider>(entities[1]).unwrap().radius, CircleCollider::default().radius);
+            assert_eq!(world.get::<BoxCollider>(entities[2]).unwrap().size, vector_xy(300., 20.));
+            assert!(world.get::<Mass>(entities[2]).is_none());
+        }
+    }
+
+    #[test]
+    fn modified_scene_is_respawned() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), PhysicsScenePlugin));
+        let scene = PhysicsScene::from_ron(b"(bodies: [Particle(pos: (0., 0.)), Particle(pos: (30., 0.))])").unwrap();
+        let handle = app.world_mut().resource_mut::<Assets<PhysicsScene>>().add(scene);
+        let root = app.world_mut().spawn(PhysicsSceneRoot(handle.clone())).id();
+        app.world_mut().insert_resource(SolverConfig { parallel: false, ..default() });
+        app.update();
+
+        let first = app.world().get::<PhysicsSceneBodies>(root).unwrap().0.clone();
+        assert_eq!(first.len(), 2);
+
+        let mut scenes = app.world_mut().resource_mut::<Assets<PhysicsScene>>();
+        scenes.get_mut(&handle).unwrap().bodies.truncate(1);
+        app.update();
+        app.update();
+
+        let second = app.world().get::<PhysicsSceneBodies>(root).unwrap().0.clone();
+        assert_eq!(second.len(), 1);
+        assert!(first.iter().all(|entity| app.world().get_entity(*entity).is_err()));
+        assert!(app.world().get::<Pos>(second[0]).is_some());
+        // The scene has no config, so reloading it leaves the solver settings alone
+        assert!(!app.world().resource::<SolverConfig>().parallel);
+    }
+}
@@ -0,0 +1,372 @@
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+use crate::*;
+
+// Physics state of a single body. Statics have no `PrevPos`, `Vel` or `Mass`,
+// so everything except `Pos` is optional.
+#[derive(Debug, Clone, Copy)]
+pub struct BodySnapshot {
+    pub pos: Pos,
+    pub prev_pos: Option<PrevPos>,
+    pub vel: Option<Vel>,
+    pub pre_solve_vel: Option<PreSolveVel>,
+    pub mass: Option<Mass>,
+    pub restitution: Option<Restitution>,
+    pub material: Option<PhysicsMaterial>,
+    pub circle_collider: Option<CircleCollider>,
+    pub box_collider: Option<BoxCollider>,
+    pub granular: Option<Granular>,
+    pub grain_spin: Option<GrainSpin>,
+    pub fluid: Option<FluidParticle>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum JointKind {
+    Distance(DistanceJoint),
+    #[cfg(not(feature = "3d"))]
+    Revolute(RevoluteJoint),
+    Prismatic(PrismaticJoint),
+    Fixed(FixedJoint),
+}
+
+// A joint with its accumulated multiplier. The body entities inside `joint` are
+// only valid in the world it was captured from, `body_a` and `body_b` are used instead.
+#[derive(Debug, Clone, Copy)]
+pub struct JointSnapshot {
+    pub joint: JointKind,
+    pub body_a: PhysicsId,
+    pub body_b: PhysicsId,
+    pub break_force: Option<BreakForce>,
+}
+
+// Like joints, the particle and joint entities inside `soft_body` are replaced
+// by the ones with these ids on restore
+#[cfg(not(feature = "3d"))]
+#[derive(Debug, Clone)]
+pub struct SoftBodySnapshot {
+    pub soft_body: SoftBody,
+    pub particles: Vec<PhysicsId>,
+    pub joints: Vec<PhysicsId>,
+}
+
+// Full simulation state, keyed by `PhysicsId` so it can be restored onto
+// entities other than the ones it was captured from
+#[derive(Debug, Clone, Default)]
+pub struct PhysicsSnapshot {
+    pub gravity: Option<Gravity>,
+    pub next_id: NextPhysicsId,
+    pub bodies: BTreeMap<PhysicsId, BodySnapshot>,
+    pub joints: BTreeMap<PhysicsId, JointSnapshot>,
+    #[cfg(not(feature = "3d"))]
+    pub soft_bodies: BTreeMap<PhysicsId, SoftBodySnapshot>,
+}
+
+type BodyQuery<'a> = (
+    &'a PhysicsId,
+    &'a Pos,
+    Option<&'a PrevPos>,
+    Option<&'a Vel>,
+    Option<&'a PreSolveVel>,
+    Option<&'a Mass>,
+    Option<&'a Restitution>,
+    Option<&'a PhysicsMaterial>,
+    Option<&'a CircleCollider>,
+    Option<&'a BoxCollider>,
+    Option<&'a Granular>,
+    Option<&'a GrainSpin>,
+    Option<&'a FluidParticle>,
+);
+
+impl PhysicsSnapshot {
+    pub fn capture(world: &mut World) -> Self {
+        let mut query = world.query::<BodyQuery>();
+        let bodies = query
+            .iter(world)
+            .map(|(id, pos, prev_pos, vel, pre_solve_vel, mass, restitution, material, circle_collider, box_collider, granular, grain_spin, fluid)| {
+                (*id, BodySnapshot {
+                    pos: *pos,
+                    prev_pos: prev_pos.copied(),
+                    vel: vel.copied(),
+                    pre_solve_vel: pre_solve_vel.copied(),
+                    mass: mass.copied(),
+                    restitution: restitution.copied(),
+                    material: material.copied(),
+                    circle_collider: circle_collider.copied(),
+                    box_collider: box_collider.copied(),
+                    granular: granular.copied(),
+                    grain_spin: grain_spin.copied(),
+                    fluid: fluid.copied(),
+                })
+            })
+            .collect();
+
+        let mut joints = BTreeMap::new();
+        capture_joints(world, JointKind::Distance, &mut joints);
+        #[cfg(not(feature = "3d"))]
+        capture_joints(world, JointKind::Revolute, &mut joints);
+        capture_joints(world, JointKind::Prismatic, &mut joints);
+        capture_joints(world, JointKind::Fixed, &mut joints);
+
+        #[cfg(not(feature = "3d"))]
+        let soft_bodies = {
+            let mut query = world.query::<(&PhysicsId, &SoftBody)>();
+            let ids = |entities: &[Entity]| entities.iter().filter_map(|entity| world.get::<PhysicsId>(*entity).copied()).collect();
+            query
+                .iter(world)
+                .map(|(id, soft_body)| {
+                    (*id, SoftBodySnapshot {
+                        soft_body: soft_body.clone(),
+                        particles: ids(&soft_body.particles),
+                        joints: ids(&soft_body.joints),
+                    })
+                })
+                .collect()
+        };
+
+        Self {
+            gravity: world.get_resource::<Gravity>().copied(),
+            next_id: world.get_resource::<NextPhysicsId>().copied().unwrap_or_default(),
+            bodies,
+            joints,
+            #[cfg(not(feature = "3d"))]
+            soft_bodies,
+        }
+    }
+
+    pub fn contains(&self, id: PhysicsId) -> bool {
+        #[cfg(not(feature = "3d"))]
+        if self.soft_bodies.contains_key(&id) {
+            return true;
+        }
+        self.bodies.contains_key(&id) || self.joints.contains_key(&id)
+    }
+
+    // Writes the snapshot back into the world. Bodies, joints and soft bodies
+    // created after the capture are despawned and ones removed since are
+    // respawned with their physics components only, so anything used for
+    // rendering has to be added again. Respawned entities are new, joints and
+    // soft bodies are linked up with them but other references are left stale.
+    pub fn restore(&self, world: &mut World) {
+        let mut query = world.query::<(Entity, &PhysicsId)>();
+        let existing: Vec<(Entity, PhysicsId)> = query.iter(world).map(|(entity, id)| (entity, *id)).collect();
+
+        let mut entities = BTreeMap::new();
+        for (entity, id) in existing {
+            if self.contains(id) {
+                entities.insert(id, entity);
+            } else if is_captured(world.entity(entity)) {
+                world.despawn(entity);
+            }
+        }
+        let mut entity = |world: &mut World, id: PhysicsId| *entities.entry(id).or_insert_with(|| world.spawn(id).id());
+
+        // Bodies first, joints and soft bodies need their entities
+        for (id, body) in self.bodies.iter() {
+            let body_entity = entity(world, *id);
+            restore_body(world.entity_mut(body_entity), body);
+        }
+        for (id, joint) in self.joints.iter() {
+            let (body_a, body_b) = (entity(world, joint.body_a), entity(world, joint.body_b));
+            let joint_entity = entity(world, *id);
+            restore_joint(world.entity_mut(joint_entity), joint, body_a, body_b);
+        }
+        #[cfg(not(feature = "3d"))]
+        for (id, snapshot) in self.soft_bodies.iter() {
+            let soft_body = SoftBody {
+                particles: snapshot.particles.iter().map(|id| entity(world, *id)).collect(),
+                joints: snapshot.joints.iter().map(|id| entity(world, *id)).collect(),
+                ..snapshot.soft_body.clone()
+            };
+            let soft_body_entity = entity(world, *id);
+            world.entity_mut(soft_body_entity).insert(soft_body);
+        }
+
+        if let Some(gravity) = self.gravity {
+            world.insert_resource(gravity);
+        }
+        world.insert_resource(self.next_id);
+    }
+}
+
+fn capture_joints<J: Joint + Copy>(world: &mut World, kind: fn(J) -> JointKind, joints: &mut BTreeMap<PhysicsId, JointSnapshot>) {
+    let mut query = world.query::<(&PhysicsId, &J, Option<&BreakForce>)>();
+    for (id, joint, break_force) in query.iter(world) {
+        let (body_a, body_b) = joint.bodies();
+        // Joints whose bodies are gone do nothing, and are left out
+        let (Some(body_a), Some(body_b)) = (world.get::<PhysicsId>(body_a), world.get::<PhysicsId>(body_b)) else {
+            continue;
+        };
+        joints.insert(*id, JointSnapshot {
+            joint: kind(*joint),
+            body_a: *body_a,
+            body_b: *body_b,
+            break_force: break_force.copied(),
+        });
+    }
+}
+
+// Whether the snapshot would have captured this entity, other entities that
+// happen to have a `PhysicsId` are left alone by `restore`
+fn is_captured(entity: EntityRef) -> bool {
+    #[cfg(not(feature = "3d"))]
+    if entity.contains::<RevoluteJoint>() || entity.contains::<SoftBody>() {
+        return true;
+    }
+    entity.contains::<Pos>() || entity.contains::<DistanceJoint>() || entity.contains::<PrismaticJoint>() || entity.contains::<FixedJoint>()
+}
+
+fn restore_body(mut entity: EntityWorldMut, body: &BodySnapshot) {
+    restore_if_changed(&mut entity, Some(body.pos));
+    restore_optional(&mut entity, body.prev_pos);
+    restore_optional(&mut entity, body.vel);
+    restore_optional(&mut entity, body.pre_solve_vel);
+    restore_optional(&mut entity, body.mass);
+    restore_optional(&mut entity, body.restitution);
+    restore_optional(&mut entity, body.material);
+    restore_if_changed(&mut entity, body.circle_collider);
+    restore_if_changed(&mut entity, body.box_collider);
+    restore_optional(&mut entity, body.granular);
+    restore_optional(&mut entity, body.grain_spin);
+    restore_optional(&mut entity, body.fluid);
+}
+
+fn restore_joint(mut entity: EntityWorldMut, snapshot: &JointSnapshot, body_a: Entity, body_b: Entity) {
+    entity.remove::<(DistanceJoint, PrismaticJoint, FixedJoint)>();
+    #[cfg(not(feature = "3d"))]
+    entity.remove::<RevoluteJoint>();
+    match snapshot.joint {
+        JointKind::Distance(joint) => insert_joint(&mut entity, joint, body_a, body_b),
+        #[cfg(not(feature = "3d"))]
+        JointKind::Revolute(joint) => insert_joint(&mut entity, joint, body_a, body_b),
+        JointKind::Prismatic(joint) => insert_joint(&mut entity, joint, body_a, body_b),
+        JointKind::Fixed(joint) => insert_joint(&mut entity, joint, body_a, body_b),
+    }
+    restore_optional(&mut entity, snapshot.break_force);
+}
+
+fn insert_joint<J: Joint>(entity: &mut EntityWorldMut, mut joint: J, body_a: Entity, body_b: Entity) {
+    joint.set_bodies(body_a, body_b);
+    entity.insert(joint);
+}
+
+fn restore_optional<T: Component>(entity: &mut EntityWorldMut, component: Option<T>) {
+    match component {
+        Some(component) => {
+            entity.insert(component);
+        }
+        None => {
+            entity.remove::<T>();
+        }
+    }
+}
+
+// Inserting counts as a change even when the value is the same, and a changed
+// static shape rebuilds the static BVH, so shapes are only written if they differ
+fn restore_if_changed<T: Component + PartialEq>(entity: &mut EntityWorldMut, component: Option<T>) {
+    if entity.get::<T>() != component.as_ref() {
+        restore_optional(entity, component);
+    }
+}
+
+// Gives every new body, joint or soft body a `PhysicsId` unless it was spawned with one
+pub(crate) fn assign_physics_id<C: Component>(
+    add: On<Add, C>,
+    ids: Query<(), With<PhysicsId>>,
+    mut next_id: ResMut<NextPhysicsId>,
+    mut commands: Commands,
+) {
+    if ids.contains(add.entity) {
+        return;
+    }
+    commands.entity(add.entity).insert(PhysicsId(next_id.0));
+    next_id.0 += 1;
+}
+
+// Keeps `NextPhysicsId` past ids given by hand, so later bodies never reuse them
+pub(crate) fn reserve_physics_id(add: On<Add, PhysicsId>, ids: Query<&PhysicsId>, mut next_id: ResMut<NextPhysicsId>) {
+    if let Ok(id) = ids.get(add.entity) {
+        next_id.0 = next_id.0.max(id.0 + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn rewinding_and_resimulating_is_bit_identical() {
+        let mut app = test_app();
+        let particles = spawn_stack(app.world_mut());
+        step(&mut app, 20);
+
+        let snapshot = PhysicsSnapshot::capture(app.world_mut());
+        step(&mut app, 30);
+        let expected = positions(&app, &particles);
+
+        snapshot.restore(app.world_mut());
+        step(&mut app, 30);
+        let resimulated = positions(&app, &particles);
+
+        for (a, b) in expected.iter().zip(resimulated.iter()) {
+            assert_eq!(a.to_array().map(Scalar::to_bits), b.to_array().map(Scalar::to_bits));
+        }
+    }
+
+    #[test]
+    fn rewinding_respawns_jointed_bodies_and_their_joints() {
+        let mut app = test_app();
+        let anchor = app.world_mut().spawn(Pos(vector_xy(0., 100.))).id();
+        let bob = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(50., 100.), Vector::ZERO, 1., 5.)).id();
+        let joint = app.world_mut().spawn((DistanceJoint::new(anchor, bob, 50.), BreakForce(1e6))).id();
+        let bob_id = *app.world().get::<PhysicsId>(bob).unwrap();
+        step(&mut app, 20);
+
+        let snapshot = PhysicsSnapshot::capture(app.world_mut());
+        step(&mut app, 30);
+        let expected = app.world().get::<Pos>(bob).unwrap().0;
+
+        // Lose both the bob and its joint, the snapshot brings them back linked up
+        app.world_mut().despawn(bob);
+        app.world_mut().despawn(joint);
+        snapshot.restore(app.world_mut());
+        step(&mut app, 30);
+
+        let mut bobs = app.world_mut().query::<(Entity, &PhysicsId, &Pos)>();
+        let (bob, _, pos) = bobs.iter(app.world()).find(|(_, id, _)| **id == bob_id).unwrap();
+        assert_eq!(pos.0.to_array().map(Scalar::to_bits), expected.to_array().map(Scalar::to_bits));
+        let mut joints = app.world_mut().query_filtered::<&DistanceJoint, With<BreakForce>>();
+        assert_eq!(joints.single(app.world()).unwrap().bodies(), (anchor, bob));
+    }
+
+    #[test]
+    fn restore_removes_and_respawns_bodies() {
+        let mut app = test_app();
+        let particles = spawn_stack(app.world_mut());
+        let removed_id = *app.world().get::<PhysicsId>(particles[0]).unwrap();
+
+        let snapshot = PhysicsSnapshot::capture(app.world_mut());
+        app.world_mut().despawn(particles[0]);
+        let added = app.world_mut().spawn(ParticleBundle::default()).id();
+
+        snapshot.restore(app.world_mut());
+
+        assert!(app.world().get_entity(added).is_err());
+        let mut ids = app.world_mut().query::<(&PhysicsId, &Pos)>();
+        let (_, pos) = ids.iter(app.world()).find(|(id, _)| **id == removed_id).unwrap();
+        assert_eq!(pos.0, snapshot.bodies[&removed_id].pos.0);
+    }
+
+    #[test]
+    fn bodies_after_a_hand_picked_id_get_new_ones() {
+        let mut app = test_app();
+        let picked = app.world_mut().spawn((ParticleBundle::default(), PhysicsId(5))).id();
+        let bodies: Vec<Entity> = (0..8).map(|_| app.world_mut().spawn(ParticleBundle::default()).id()).collect();
+
+        let mut ids: Vec<PhysicsId> = bodies.iter().map(|entity| *app.world().get::<PhysicsId>(*entity).unwrap()).collect();
+        ids.push(*app.world().get::<PhysicsId>(picked).unwrap());
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 9, "ids {:?}", ids);
+    }
+}
@@ -0,0 +1,327 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+use crate::math::consts::TAU;
+use crate::*;
+
+// A deformable body made of ordinary particles held together by `DistanceJoint`s.
+// Rings also keep their enclosed area, so they behave like inflated blobs, and
+// grids are braced with diagonal joints so they keep their shape.
+//
+// The particles collide with everything else, including each other, through
+// the usual circle passes. The `SoftBody` entity itself has no `Pos`; add a
+// `Mesh2d` made with `SoftBodyBuilder::mesh` to it and the mesh follows the particles.
+#[derive(Component, Debug, Clone)]
+pub struct SoftBody {
+    pub particles: Vec<Entity>,
+    pub joints: Vec<Entity>,
+    pub shape: SoftBodyShape,
+    pub area: Option<AreaConstraint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoftBodyShape {
+    // Particles go counter-clockwise around the ring
+    Ring,
+    // Particles are stored row by row, from the bottom left
+    Grid { columns: usize, rows: usize },
+}
+
+// Keeps the area enclosed by a ring at `rest_area`
+#[derive(Debug, Clone, Copy)]
+pub struct AreaConstraint {
+    pub rest_area: Scalar,
+    pub compliance: Scalar,
+    pub(crate) lambda: Scalar,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftBodyBuilder {
+    shape: SoftBodyShape,
+    positions: Vec<Vector>,
+    particle_mass: Scalar,
+    particle_radius: Scalar,
+    compliance: Scalar,
+    area_compliance: Scalar,
+    pressure: Scalar,
+}
+
+impl SoftBodyBuilder {
+    // `segments` particles evenly spaced on a circle, just touching their neighbours.
+    // Panics with fewer than 3 segments, which enclose no area.
+    pub fn ring(center: Vector, radius: Scalar, segments: usize) -> Self {
+        assert!(segments >= 3, "A soft body ring needs at least 3 segments, got {}", segments);
+        let positions: Vec<Vector> = (0..segments)
+            .map(|i| center + Vector::from_angle(TAU * i as Scalar / segments as Scalar) * radius)
+            .collect();
+        let spacing = positions[0].distance(positions[1 % segments]);
+        Self::new(SoftBodyShape::Ring, positions, spacing / 2.)
+    }
+
+    // A `columns` by `rows` lattice of touching particles with its bottom left particle at `origin`
+    pub fn grid(origin: Vector, columns: usize, rows: usize, spacing: Scalar) -> Self {
+        let positions = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| origin + Vector::new(column as Scalar, row as Scalar) * spacing))
+            .collect();
+        Self::new(SoftBodyShape::Grid { columns, rows }, positions, spacing / 2.)
+    }
+
+    fn new(shape: SoftBodyShape, positions: Vec<Vector>, particle_radius: Scalar) -> Self {
+        Self {
+            shape,
+            positions,
+            particle_mass: 1.,
+            particle_radius,
+            compliance: 0.,
+            area_compliance: 0.,
+            pressure: 1.,
+        }
+    }
+
+    pub fn with_particle_mass(self, particle_mass: Scalar) -> Self {
+        Self { particle_mass, ..self }
+    }
+
+    pub fn with_particle_radius(self, particle_radius: Scalar) -> Self {
+        Self { particle_radius, ..self }
+    }
+
+    // Compliance of the joints between the particles
+    pub fn with_compliance(self, compliance: Scalar) -> Self {
+        Self { compliance, ..self }
+    }
+
+    // Compliance of a ring's area constraint, and how much the ring is inflated
+    // past its initial area (1 keeps it as spawned)
+    pub fn with_area(self, area_compliance: Scalar, pressure: Scalar) -> Self {
+        Self { area_compliance, pressure, ..self }
+    }
+
+    // Pairs of particle indices linked by a joint
+    fn links(&self) -> Vec<(usize, usize)> {
+        match self.shape {
+            SoftBodyShape::Ring => {
+                let n = self.positions.len();
+                (0..n).map(|i| (i, (i + 1) % n)).collect()
+            }
+            SoftBodyShape::Grid { columns, rows } => {
+                let index = |column: usize, row: usize| row * columns + column;
+                let mut links = Vec::new();
+                for row in 0..rows {
+                    for column in 0..columns {
+                        if column + 1 < columns {
+                            links.push((index(column, row), index(column + 1, row)));
+                        }
+                        if row + 1 < rows {
+                            links.push((index(column, row), index(column, row + 1)));
+                        }
+                        if column + 1 < columns && row + 1 < rows {
+                            links.push((index(column, row), index(column + 1, row + 1)));
+                            links.push((index(column + 1, row), index(column, row + 1)));
+                        }
+                    }
+                }
+                links
+            }
+        }
+    }
+
+    // Spawns the particles, the joints between them and the `SoftBody` entity, which is returned
+    pub fn spawn(&self, commands: &mut Commands) -> Entity {
+        let particles: Vec<Entity> = self.positions
+            .iter()
+            .map(|pos| {
+                commands.spawn((
+                    Name::new("Soft Body Particle"),
+                    ParticleBundle::new_with_pos_vel_mass_radius(*pos, Vector::ZERO, self.particle_mass, self.particle_radius),
+                )).id()
+            })
+            .collect();
+
+        let joints = self.links()
+            .into_iter()
+            .map(|(a, b)| {
+                let length = self.positions[a].distance(self.positions[b]);
+                commands.spawn(DistanceJoint::new(particles[a], particles[b], length).with_compliance(self.compliance)).id()
+            })
+            .collect();
+
+        let area = (self.shape == SoftBodyShape::Ring).then(|| AreaConstraint {
+            rest_area: polygon_area(&self.positions) * self.pressure,
+            compliance: self.area_compliance,
+            lambda: 0.,
+        });
+
+        commands.spawn((
+            Name::new("Soft Body"),
+            SoftBody { particles, joints, shape: self.shape, area },
+            Transform::default(),
+        )).id()
+    }
+
+    // A mesh covering the body as spawned, in world space. Rings are a triangle fan
+    // around an extra vertex at their center.
+    pub fn mesh(&self) -> Mesh {
+        let mut positions = self.positions.clone();
+        let indices: Vec<u32> = match self.shape {
+            SoftBodyShape::Ring => {
+                let n = positions.len() as u32;
+                positions.push(centroid(&self.positions));
+                (0..n).flat_map(|i| [n, i, (i + 1) % n]).collect()
+            }
+            SoftBodyShape::Grid { columns, rows } => {
+                let index = |column: usize, row: usize| (row * columns + column) as u32;
+                (0..rows.saturating_sub(1))
+                    .flat_map(|row| (0..columns.saturating_sub(1)).map(move |column| (column, row)))
+                    .flat_map(|(column, row)| {
+                        let (a, b) = (index(column, row), index(column + 1, row));
+                        let (c, d) = (index(column + 1, row + 1), index(column, row + 1));
+                        [a, b, c, a, c, d]
+                    })
+                    .collect()
+            }
+        };
+
+        let min = positions.iter().copied().reduce(Vector::min).unwrap_or_default();
+        let max = positions.iter().copied().reduce(Vector::max).unwrap_or_default();
+        let uvs: Vec<[f32; 2]> = positions
+            .iter()
+            .map(|pos| {
+                let uv = to_render((*pos - min) / (max - min).max(Vector::splat(Scalar::EPSILON)));
+                [uv.x, 1. - uv.y]
+            })
+            .collect();
+
+        Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, mesh_positions(&positions))
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+            .with_inserted_indices(Indices::U32(indices))
+    }
+}
+
+fn centroid(positions: &[Vector]) -> Vector {
+    positions.iter().sum::<Vector>() / positions.len().max(1) as Scalar
+}
+
+// Signed area, positive for counter-clockwise polygons
+fn polygon_area(positions: &[Vector]) -> Scalar {
+    let n = positions.len();
+    (0..n).map(|i| positions[i].perp_dot(positions[(i + 1) % n])).sum::<Scalar>() / 2.
+}
+
+fn mesh_positions(positions: &[Vector]) -> Vec<[f32; 3]> {
+    positions.iter().map(|pos| to_render(*pos).extend(0.).to_array()).collect()
+}
+
+// XPBD area constraint from "Detailed Rigid Body Simulation with Extended
+// Position Based Dynamics": each particle moves along its gradient of the area,
+// which is perpendicular to the line between its two neighbours
+pub(crate) fn solve_soft_bodies(
+    mut soft_bodies: Query<(Entity, &mut SoftBody)>,
+    mut particles: Query<(&mut Pos, &Mass)>,
+    ids: Query<&PhysicsId>,
+    config: Res<SolverConfig>,
+) {
+    let mut soft_bodies: Vec<_> = soft_bodies.iter_mut().collect();
+    if config.deterministic {
+        soft_bodies.sort_by_key(|(entity, _)| ids.get(*entity).ok().copied());
+    }
+    for (_, soft_body) in soft_bodies.iter_mut() {
+        let soft_body = &mut **soft_body;
+        let Some(area) = soft_body.area.as_mut() else {
+            continue;
+        };
+
+        let bodies: Vec<(Entity, Vector, Scalar)> = soft_body.particles
+            .iter()
+            .filter_map(|entity| particles.get(*entity).ok().map(|(pos, mass)| (*entity, pos.0, 1. / mass.0)))
+            .collect();
+        let n = bodies.len();
+        if n < 3 {
+            continue;
+        }
+
+        let positions: Vec<Vector> = bodies.iter().map(|(_, pos, _)| *pos).collect();
+        let gradients: Vec<Vector> = (0..n)
+            .map(|i| (positions[(i + n - 1) % n] - positions[(i + 1) % n]).perp() / 2.)
+            .collect();
+
+        let c = polygon_area(&positions) - area.rest_area;
+        let w_sum: Scalar = bodies.iter().zip(gradients.iter()).map(|((_, _, w), gradient)| w * gradient.length_squared()).sum();
+        let alpha = area.compliance / (DELTA_TIME * DELTA_TIME);
+        if w_sum + alpha <= Scalar::EPSILON {
+            continue;
+        }
+        let delta_lambda = (-c - alpha * area.lambda) / (w_sum + alpha);
+        area.lambda += delta_lambda;
+
+        for ((entity, _, w), gradient) in bodies.iter().zip(gradients.iter()) {
+            if let Ok((mut pos, _)) = particles.get_mut(*entity) {
+                pos.0 += *gradient * *w * delta_lambda;
+            }
+        }
+    }
+}
+
+pub(crate) fn reset_soft_bodies(mut soft_bodies: Query<&mut SoftBody>) {
+    for mut soft_body in soft_bodies.iter_mut() {
+        if let Some(area) = soft_body.area.as_mut() {
+            area.lambda = 0.;
+        }
+    }
+}
+
+pub(crate) fn update_soft_body_meshes(
+    soft_bodies: Query<(&SoftBody, &Mesh2d)>,
+    particles: Query<&Pos>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (soft_body, mesh) in soft_bodies.iter() {
+        let Some(mesh) = meshes.get_mut(&mesh.0) else {
+            continue;
+        };
+        let mut positions: Vec<Vector> = soft_body.particles
+            .iter()
+            .map(|entity| particles.get(*entity).map_or(Vector::ZERO, |pos| pos.0))
+            .collect();
+        if soft_body.shape == SoftBodyShape::Ring {
+            positions.push(centroid(&positions));
+        }
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, mesh_positions(&positions));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    #[should_panic(expected = "at least 3 segments")]
+    fn ring_needs_three_segments() {
+        SoftBodyBuilder::ring(Vector::ZERO, 50., 2);
+    }
+
+    #[test]
+    fn dropped_ring_keeps_its_area_on_the_floor() {
+        let mut app = test_app();
+        app.world_mut().spawn(StaticBoxBundle {
+            pos: Pos(Vector::new(0., -60.)),
+            collider: BoxCollider { size: Vector::new(300., 100.) },
+            ..default()
+        });
+        let builder = SoftBodyBuilder::ring(Vector::new(0., 60.), 30., 16);
+        let soft_body = builder.spawn(&mut app.world_mut().commands());
+        app.world_mut().flush();
+        step(&mut app, 120);
+
+        let particles = app.world().get::<SoftBody>(soft_body).unwrap().particles.clone();
+        let positions = positions(&app, &particles);
+        let rest_area = polygon_area(&builder.positions);
+        let area = polygon_area(&positions);
+        assert!((area - rest_area).abs() < 0.1 * rest_area, "area {} of {}", area, rest_area);
+        // Resting on the floor, which has its top at y = -10
+        let bottom = positions.iter().map(|pos| pos.y).fold(Scalar::MAX, Scalar::min);
+        assert!((bottom + 10. - builder.particle_radius).abs() < 2., "bottom {}", bottom);
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+use crate::*;
+
+// Counters and timings of the last fixed step. Also published to the
+// `DiagnosticsStore` under the `xpbd/` paths below.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct PhysicsStats {
+    pub body_count: usize,
+    pub collision_pairs: usize,
+    pub contacts: usize,
+    pub static_contacts: usize,
+    pub broad_phase_time: Duration,
+    pub integrate_time: Duration,
+    pub substeps_time: Duration,
+    pub velocity_solve_time: Duration,
+    pub kinetic_energy: Scalar,
+    // Deepest overlap resolved by any position solve during the step
+    pub max_penetration: Scalar,
+    last_lap: Option<Instant>,
+}
+
+impl PhysicsStats {
+    pub const BODY_COUNT: DiagnosticPath = DiagnosticPath::const_new("xpbd/body_count");
+    pub const COLLISION_PAIRS: DiagnosticPath = DiagnosticPath::const_new("xpbd/collision_pairs");
+    pub const CONTACTS: DiagnosticPath = DiagnosticPath::const_new("xpbd/contacts");
+    pub const STATIC_CONTACTS: DiagnosticPath = DiagnosticPath::const_new("xpbd/static_contacts");
+    pub const BROAD_PHASE_TIME: DiagnosticPath = DiagnosticPath::const_new("xpbd/broad_phase_time");
+    pub const INTEGRATE_TIME: DiagnosticPath = DiagnosticPath::const_new("xpbd/integrate_time");
+    pub const SUBSTEPS_TIME: DiagnosticPath = DiagnosticPath::const_new("xpbd/substeps_time");
+    pub const VELOCITY_SOLVE_TIME: DiagnosticPath = DiagnosticPath::const_new("xpbd/velocity_solve_time");
+    pub const KINETIC_ENERGY: DiagnosticPath = DiagnosticPath::const_new("xpbd/kinetic_energy");
+    pub const MAX_PENETRATION: DiagnosticPath = DiagnosticPath::const_new("xpbd/max_penetration");
+}
+
+pub(crate) fn register_physics_diagnostics(app: &mut App) {
+    for path in [PhysicsStats::BODY_COUNT, PhysicsStats::COLLISION_PAIRS, PhysicsStats::CONTACTS, PhysicsStats::STATIC_CONTACTS] {
+        app.register_diagnostic(Diagnostic::new(path));
+    }
+    for path in [PhysicsStats::BROAD_PHASE_TIME, PhysicsStats::INTEGRATE_TIME, PhysicsStats::SUBSTEPS_TIME, PhysicsStats::VELOCITY_SOLVE_TIME] {
+        app.register_diagnostic(Diagnostic::new(path).with_suffix("ms"));
+    }
+    app.register_diagnostic(Diagnostic::new(PhysicsStats::KINETIC_ENERGY));
+    app.register_diagnostic(Diagnostic::new(PhysicsStats::MAX_PENETRATION));
+}
+
+pub(crate) fn start_stats_clock(mut stats: ResMut<PhysicsStats>) {
+    stats.max_penetration = 0.;
+    stats.last_lap = Some(Instant::now());
+}
+
+// Stores the time since the previous lap into the chosen timing field
+pub(crate) fn lap(stage: fn(&mut PhysicsStats) -> &mut Duration) -> impl FnMut(ResMut<PhysicsStats>) {
+    move |mut stats| {
+        let now = Instant::now();
+        let elapsed = stats.last_lap.map_or(Duration::ZERO, |last_lap| now - last_lap);
+        *stage(&mut stats) = elapsed;
+        stats.last_lap = Some(now);
+    }
+}
+
+pub(crate) fn update_stats(
+    bodies: Query<(&Vel, &Mass)>,
+    collision_pairs: Res<CollisionPairs>,
+    contacts: Res<Contacts>,
+    static_contacts: Res<StaticContacts>,
+    mut stats: ResMut<PhysicsStats>,
+    mut diagnostics: Diagnostics,
+) {
+    stats.body_count = bodies.iter().len();
+    stats.collision_pairs = collision_pairs.0.len();
+    stats.contacts = contacts.0.len();
+    stats.static_contacts = static_contacts.0.len();
+    stats.kinetic_energy = bodies.iter().map(|(vel, mass)| 0.5 * mass.0 * vel.0.length_squared()).sum();
+
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.;
+    diagnostics.add_measurement(&PhysicsStats::BODY_COUNT, || stats.body_count as f64);
+    diagnostics.add_measurement(&PhysicsStats::COLLISION_PAIRS, || stats.collision_pairs as f64);
+    diagnostics.add_measurement(&PhysicsStats::CONTACTS, || stats.contacts as f64);
+    diagnostics.add_measurement(&PhysicsStats::STATIC_CONTACTS, || stats.static_contacts as f64);
+    diagnostics.add_measurement(&PhysicsStats::BROAD_PHASE_TIME, || millis(stats.broad_phase_time));
+    diagnostics.add_measurement(&PhysicsStats::INTEGRATE_TIME, || millis(stats.integrate_time));
+    diagnostics.add_measurement(&PhysicsStats::SUBSTEPS_TIME, || millis(stats.substeps_time));
+    diagnostics.add_measurement(&PhysicsStats::VELOCITY_SOLVE_TIME, || millis(stats.velocity_solve_time));
+    diagnostics.add_measurement(&PhysicsStats::KINETIC_ENERGY, || to_f64(stats.kinetic_energy));
+    diagnostics.add_measurement(&PhysicsStats::MAX_PENETRATION, || to_f64(stats.max_penetration));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::diagnostic::DiagnosticsStore;
+    use crate::test_utils::*;
+
+    #[test]
+    fn stats_are_collected_and_published() {
+        let mut app = test_app();
+        let particles = spawn_stack(app.world_mut());
+        step(&mut app, 10);
+
+        let stats = app.world().resource::<PhysicsStats>();
+        assert_eq!(stats.body_count, particles.len());
+        assert!(stats.collision_pairs > 0);
+        // The columns rest on each other as well as on the floor
+        assert!(stats.contacts > 0);
+        assert!(stats.static_contacts > 0);
+        assert!(stats.kinetic_energy > 0.);
+        assert!(stats.max_penetration > 0.);
+        assert!(stats.substeps_time > Duration::ZERO);
+
+        let store = app.world().resource::<DiagnosticsStore>();
+        let body_count = store.get(&PhysicsStats::BODY_COUNT).and_then(|diagnostic| diagnostic.value());
+        assert_eq!(body_count, Some(particles.len() as f64));
+    }
+}
@@ -0,0 +1,40 @@
+use bevy::prelude::*;
+use crate::*;
+
+pub(crate) fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(XPBDPlugin)
+        .insert_resource(Gravity(vector_xy(0., -500.)));
+    app
+}
+
+// A floor with a few columns of particles resting on it, like `ball_stacking`
+pub(crate) fn spawn_stack(world: &mut World) -> Vec<Entity> {
+    spawn_stack_at(world, Vector::ZERO)
+}
+
+pub(crate) fn spawn_stack_at(world: &mut World, offset: Vector) -> Vec<Entity> {
+    world.spawn(StaticBoxBundle {
+        pos: Pos(offset + vector_xy(0., -60.)),
+        collider: BoxCollider { size: box_size(300., 100.) },
+        ..default()
+    });
+    (0..40)
+        .map(|i| {
+            let pos = offset + vector_xy((i % 5) as Scalar * 25. - 50., (i / 5) as Scalar * 21.);
+            world
+                .spawn(ParticleBundle::new_with_pos_vel_mass_radius(pos, Vector::ZERO, 10., 10.))
+                .id()
+        })
+        .collect()
+}
+
+pub(crate) fn step(app: &mut App, steps: usize) {
+    for _ in 0..steps {
+        app.world_mut().run_schedule(FixedUpdate);
+    }
+}
+
+pub(crate) fn positions(app: &App, entities: &[Entity]) -> Vec<Vector> {
+    entities.iter().map(|e| app.world().get::<Pos>(*e).unwrap().0).collect()
+}
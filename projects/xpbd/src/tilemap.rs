@@ -0,0 +1,193 @@
+use bevy::image::TextureAccessError;
+use bevy::prelude::*;
+use crate::*;
+
+// Rectangle of solid tiles, in tiles, counted from the top left of the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRect {
+    pub column: usize,
+    pub row: usize,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+// Turns a grid of solid and empty tiles into as few static boxes as it can,
+// merging neighbouring solid tiles into rectangles: runs along a row first,
+// then down as far as the rows below are solid over the whole run. `origin` is
+// the top left corner of the map, rows go downward.
+#[derive(Debug, Clone)]
+pub struct TilemapColliderBuilder {
+    solid: Vec<bool>,
+    columns: usize,
+    rows: usize,
+    tile_size: Scalar,
+    origin: Vector,
+    restitution: Option<Scalar>,
+}
+
+impl TilemapColliderBuilder {
+    // `solid` is stored row by row from the top
+    pub fn from_grid(columns: usize, rows: usize, solid: Vec<bool>) -> Self {
+        assert_eq!(solid.len(), columns * rows, "Tile grid should have {} by {} tiles", columns, rows);
+        Self {
+            solid,
+            columns,
+            rows,
+            tile_size: 1.,
+            origin: Vector::ZERO,
+            restitution: None,
+        }
+    }
+
+    // One tile per pixel, solid where the pixel's alpha is at least
+    // `alpha_threshold`. Fails on images whose pixels Bevy cannot read, such as
+    // compressed formats, rather than treating every tile as empty.
+    pub fn from_image(image: &Image, alpha_threshold: f32) -> Result<Self, TextureAccessError> {
+        let (columns, rows) = (image.width() as usize, image.height() as usize);
+        let solid = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let color = image.get_color_at(column as u32, row as u32)?;
+                Ok(color.alpha() >= alpha_threshold)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::from_grid(columns, rows, solid))
+    }
+
+    pub fn with_tile_size(self, tile_size: Scalar) -> Self {
+        Self { tile_size, ..self }
+    }
+
+    pub fn with_origin(self, origin: Vector) -> Self {
+        Self { origin, ..self }
+    }
+
+    pub fn with_restitution(self, restitution: Scalar) -> Self {
+        Self { restitution: Some(restitution), ..self }
+    }
+
+    pub fn is_solid(&self, column: usize, row: usize) -> bool {
+        column < self.columns && row < self.rows && self.solid[row * self.columns + column]
+    }
+
+    pub fn rects(&self) -> Vec<TileRect> {
+        let mut covered = vec![false; self.solid.len()];
+        let free = |covered: &[bool], column: usize, row: usize| self.is_solid(column, row) && !covered[row * self.columns + column];
+
+        let mut rects = Vec::new();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                if !free(&covered, column, row) {
+                    continue;
+                }
+                let columns = (column..self.columns).take_while(|c| free(&covered, *c, row)).count();
+                let rows = (row..self.rows)
+                    .take_while(|r| (column..column + columns).all(|c| free(&covered, c, *r)))
+                    .count();
+
+                for r in row..row + rows {
+                    covered[r * self.columns + column..r * self.columns + column + columns].fill(true);
+                }
+                rects.push(TileRect { column, row, columns, rows });
+            }
+        }
+        rects
+    }
+
+    pub fn spawn(&self, commands: &mut Commands) -> Vec<Entity> {
+        self.rects()
+            .into_iter()
+            .map(|rect| {
+                let size = vector_xy(rect.columns as Scalar, rect.rows as Scalar) * self.tile_size;
+                let corner = self.origin + vector_xy(rect.column as Scalar, -(rect.row as Scalar)) * self.tile_size;
+                let mut bundle = StaticBoxBundle {
+                    pos: Pos(corner + vector_xy(size.x, -size.y) / 2.),
+                    collider: BoxCollider { size },
+                    ..default()
+                };
+                if let Some(restitution) = self.restitution {
+                    bundle.restitution = Restitution(restitution);
+                }
+                commands.spawn((Name::new("Tiles"), bundle)).id()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::RenderAssetUsages;
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+    use crate::test_utils::*;
+
+    #[test]
+    fn solid_tiles_are_covered_exactly_once_by_few_boxes() {
+        // A 200 by 200 level: walls all around, a floor three tiles thick and a staircase
+        let (columns, rows) = (200, 200);
+        let solid = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| column == 0 || column == columns - 1 || row == 0 || row >= rows - 3 || (column > 100 && row > 150 + (200 - column) / 4))
+            .collect();
+        let builder = TilemapColliderBuilder::from_grid(columns, rows, solid);
+
+        let rects = builder.rects();
+        let mut covered = vec![0; columns * rows];
+        for rect in rects.iter() {
+            for row in rect.row..rect.row + rect.rows {
+                for column in rect.column..rect.column + rect.columns {
+                    assert!(builder.is_solid(column, row));
+                    covered[row * columns + column] += 1;
+                }
+            }
+        }
+        for (i, count) in covered.iter().enumerate() {
+            assert_eq!(*count, builder.is_solid(i % columns, i / columns) as i32, "tile {}", i);
+        }
+        assert!(rects.len() < 50, "{} boxes", rects.len());
+    }
+
+    #[test]
+    fn ball_lands_on_tiles_from_an_image() {
+        // A 4 by 2 image with an opaque bottom row
+        let pixels = [[0u8, 0, 0, 0]; 4].into_iter().chain([[0, 0, 0, 255]; 4]).flatten().collect();
+        let image = Image::new(
+            Extent3d { width: 4, height: 2, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            pixels,
+            TextureFormat::Rgba8Unorm,
+            RenderAssetUsages::default(),
+        );
+
+        let mut app = test_app();
+        let tiles = TilemapColliderBuilder::from_image(&image, 0.5)
+            .unwrap()
+            .with_tile_size(20.)
+            .with_origin(vector_xy(-40., 20.))
+            .spawn(&mut app.world_mut().commands());
+        app.world_mut().flush();
+        let ball = app.world_mut().spawn(ParticleBundle::new_with_pos_vel_mass_radius(vector_xy(0., 50.), Vector::ZERO, 1., 5.)).id();
+
+        step(&mut app, 100);
+
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(app.world().get::<BoxCollider>(tiles[0]).unwrap().size, vector_xy(80., 20.));
+        // The bottom row spans -20 to 0, so the ball rests on top of it
+        let pos = app.world().get::<Pos>(ball).unwrap().0;
+        assert!((pos.y - 5.).abs() < 0.5, "ball at {}", pos);
+    }
+
+    #[test]
+    fn image_in_an_unreadable_format_is_an_error() {
+        let image = Image::new(
+            Extent3d { width: 2, height: 1, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            vec![0, 127],
+            TextureFormat::R8Snorm,
+            RenderAssetUsages::default(),
+        );
+
+        let error = TilemapColliderBuilder::from_image(&image, 0.5).unwrap_err();
+        assert!(matches!(error, TextureAccessError::UnsupportedTextureFormat(TextureFormat::R8Snorm)), "{}", error);
+    }
+}
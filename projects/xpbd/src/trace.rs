@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use crate::*;
+
+#[cfg(not(feature = "3d"))]
+const HEADER: &str = "step,id,pos_x,pos_y,vel_x,vel_y";
+#[cfg(feature = "3d")]
+const HEADER: &str = "step,id,pos_x,pos_y,pos_z,vel_x,vel_y,vel_z";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceSample {
+    pub id: PhysicsId,
+    pub pos: Vector,
+    pub vel: Vector,
+}
+
+// Writes every body's `Pos` and `Vel` after each fixed step as CSV rows, in
+// `PhysicsId` order so traces from different runs can be diffed line by line
+#[derive(Resource)]
+pub struct TraceRecorder {
+    writer: Box<dyn Write + Send + Sync>,
+    step: u64,
+}
+
+impl TraceRecorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn new(writer: impl Write + Send + Sync + 'static) -> io::Result<Self> {
+        let mut writer: Box<dyn Write + Send + Sync> = Box::new(writer);
+        writeln!(writer, "{}", HEADER)?;
+        Ok(Self { writer, step: 0 })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn write_step(&mut self, samples: &[TraceSample]) -> io::Result<()> {
+        for sample in samples {
+            let components: Vec<String> = sample.pos.to_array()
+                .into_iter()
+                .chain(sample.vel.to_array())
+                .map(|component| component.to_string())
+                .collect();
+            writeln!(self.writer, "{},{},{}", self.step, sample.id.0, components.join(","))?;
+        }
+        self.step += 1;
+        Ok(())
+    }
+}
+
+// While this resource exists the solver is skipped and bodies are moved along
+// the recorded trace instead, one recorded step per fixed step
+#[derive(Resource, Debug, Default)]
+pub struct TraceReplay {
+    pub steps: Vec<Vec<TraceSample>>,
+    pub current: usize,
+}
+
+impl TraceReplay {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut steps: Vec<Vec<TraceSample>> = Vec::new();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if (number == 0 && line == HEADER) || line.is_empty() {
+                continue;
+            }
+
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid trace line {}: {:?}", number + 1, line));
+            let fields: Vec<&str> = line.split(',').collect();
+            let [step, id, ref components @ ..] = fields[..] else {
+                return Err(invalid());
+            };
+            if components.len() != 2 * DIMENSIONS {
+                return Err(invalid());
+            }
+            let components = components
+                .iter()
+                .map(|field| field.parse::<Scalar>().map_err(|_| invalid()))
+                .collect::<io::Result<Vec<Scalar>>>()?;
+
+            let step = step.parse::<usize>().map_err(|_| invalid())?;
+            if step >= steps.len() {
+                steps.resize_with(step + 1, Vec::new);
+            }
+            steps[step].push(TraceSample {
+                id: PhysicsId(id.parse().map_err(|_| invalid())?),
+                pos: Vector::from_slice(&components[..DIMENSIONS]),
+                vel: Vector::from_slice(&components[DIMENSIONS..]),
+            });
+        }
+
+        for samples in steps.iter_mut() {
+            samples.sort_by_key(|sample| sample.id);
+        }
+        Ok(Self { steps, current: 0 })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current >= self.steps.len()
+    }
+}
+
+pub(crate) fn record_trace(
+    query: Query<(&PhysicsId, &Pos, Option<&Vel>)>,
+    mut recorder: ResMut<TraceRecorder>,
+) {
+    let mut samples: Vec<TraceSample> = query
+        .iter()
+        .map(|(id, pos, vel)| TraceSample {
+            id: *id,
+            pos: pos.0,
+            vel: vel.map_or(Vector::ZERO, |vel| vel.0),
+        })
+        .collect();
+    samples.sort_by_key(|sample| sample.id);
+
+    if let Err(err) = recorder.write_step(&samples) {
+        error!("Could not write physics trace: {}", err);
+    }
+}
+
+pub(crate) fn replay_trace(
+    mut query: Query<(&PhysicsId, &mut Pos, Option<&mut PrevPos>, Option<&mut Vel>)>,
+    mut replay: ResMut<TraceReplay>,
+) {
+    if replay.is_finished() {
+        return;
+    }
+
+    let samples = &replay.steps[replay.current];
+    for (id, mut pos, prev_pos, vel) in query.iter_mut() {
+        let Ok(index) = samples.binary_search_by_key(id, |sample| sample.id) else {
+            continue;
+        };
+        // Keep `PrevPos` moving along too, so interpolated rendering works during replay
+        if let Some(mut prev_pos) = prev_pos {
+            prev_pos.0 = pos.0;
+        }
+        pos.0 = samples[index].pos;
+        if let Some(mut vel) = vel {
+            vel.0 = samples[index].vel;
+        }
+    }
+    replay.current += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn replaying_a_recorded_trace_reproduces_positions() {
+        let path = std::env::temp_dir().join(format!("xpbd_trace_{}.csv", std::process::id()));
+
+        let mut app = test_app();
+        app.insert_resource(TraceRecorder::create(&path).unwrap());
+        let particles = spawn_stack(app.world_mut());
+        step(&mut app, 30);
+        let expected = positions(&app, &particles);
+        app.world_mut().resource_mut::<TraceRecorder>().flush().unwrap();
+
+        let mut replay_app = test_app();
+        replay_app.insert_resource(TraceReplay::load(&path).unwrap());
+        let replayed = spawn_stack(replay_app.world_mut());
+        step(&mut replay_app, 30);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(replay_app.world().resource::<TraceReplay>().is_finished());
+        assert_eq!(expected, positions(&replay_app, &replayed));
+    }
+}